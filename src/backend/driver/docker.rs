@@ -1,19 +1,31 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
 
-use anyhow::{anyhow, bail, Context, Result};
+use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use regex::Regex;
+use serde_derive::Deserialize;
 use tokio::process::Command;
-use which::which;
 
-use crate::backend::{BuildArg, Driver, EnvVar, Image, Mount, Secret, Ssh};
-use crate::config::{Reference, RegistrySource};
+use crate::backend::progress::{self, Progress};
+use crate::backend::{
+    BuildArg, Driver, EnvVar, Image, Mount, RunningContainer, Seccomp, Secret, Ssh,
+};
+use crate::config::{Digest, HealthCheck, Platform, Reference, RegistrySource};
 
+#[derive(Clone)]
 pub struct DockerCliCompatible {
     binary: PathBuf,
     argument: Option<PathBuf>,
+    run_arguments: Vec<String>,
+    /// Argument to pull over plain HTTP, docker only allows this in the daemon configuration
+    insecure_argument: Option<&'static str>,
+    /// Whether `buildx imagetools` is available to inspect remote images
+    imagetools: bool,
 }
 
 pub struct DockerImage(String);
@@ -24,105 +36,1035 @@ impl Image for DockerImage {
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PsLine {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(default)]
+    names: String,
+    image: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    running_for: String,
+    #[serde(default)]
+    ports: String,
+}
+
+impl From<PsLine> for RunningContainer {
+    fn from(line: PsLine) -> Self {
+        RunningContainer {
+            id: line.id,
+            name: line.names,
+            image: line.image,
+            status: line.status,
+            running_for: line.running_for,
+            ports: line.ports,
+        }
+    }
+}
+
+fn parse_ps(output: &str) -> Result<Vec<RunningContainer>> {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str::<PsLine>(line)
+                .map(RunningContainer::from)
+                .with_context(|| format!("could not parse container listing `{}`", line))
+        })
+        .collect()
+}
+
+const BUILD_ERROR_LINES: usize = 50;
+
+/// Extract the errors from the build output, falls back to the tail of the output
+fn build_errors(output: &str) -> String {
+    let mut errors = vec![];
+    for line in output.lines() {
+        let line = line.trim();
+        if line.starts_with("ERROR") || line.starts_with("error:") {
+            errors.push(line.to_string());
+            continue;
+        }
+
+        // BuildKit JSON progress reports the failed steps in the error field of the vertexes
+        if let Ok(frame) = serde_json::from_str::<serde_json::Value>(line) {
+            let vertexes = frame
+                .get("vertexes")
+                .and_then(|vertexes| vertexes.as_array());
+            for vertex in vertexes.into_iter().flatten() {
+                if let Some(error) = vertex.get("error").and_then(|error| error.as_str()) {
+                    let step = vertex
+                        .get("name")
+                        .and_then(|name| name.as_str())
+                        .unwrap_or("unknown step");
+                    errors.push(format!("{}: {}", step, error));
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        let lines: Vec<&str> = output.lines().collect();
+        let start = lines.len().saturating_sub(BUILD_ERROR_LINES);
+        return lines[start..].join("\n");
+    }
+    errors.join("\n")
+}
+
+/// Single quotes the argument for a POSIX shell
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
 impl DockerCliCompatible {
-    pub fn resolve_with_supported_binary() -> Result<Self> {
-        // TODO, make this more robust
-        // Should also configure docker's context (where applicable)
-        let clients = vec!["colima", "lima", "nerdctl", "docker", "podman"];
-        let first_supported = clients
-            .into_iter()
-            .map(|client| (client, which(client)))
-            .find(|(_client, binary)| binary.is_ok());
+    pub fn binary(&self) -> &Path {
+        &self.binary
+    }
+
+    /// Arguments of the client, the runtimes of lima and colima are reached through `nerdctl`
+    pub fn new(client: &str, binary: PathBuf) -> Self {
+        match client {
+            "colima" => DockerCliCompatible {
+                binary,
+                argument: Some("nerdctl".into()),
+                run_arguments: vec![],
+                insecure_argument: Some("--insecure-registry"),
+                imagetools: false,
+            },
+            "lima" => DockerCliCompatible {
+                binary,
+                argument: Some("nerdctl".into()),
+                run_arguments: vec![],
+                insecure_argument: Some("--insecure-registry"),
+                imagetools: false,
+            },
+            "podman" => DockerCliCompatible {
+                binary,
+                argument: None,
+                run_arguments: vec![],
+                insecure_argument: Some("--tls-verify=false"),
+                imagetools: false,
+            },
+            "nerdctl" => DockerCliCompatible {
+                binary,
+                argument: None,
+                run_arguments: vec![],
+                insecure_argument: Some("--insecure-registry"),
+                imagetools: false,
+            },
+            _ => DockerCliCompatible {
+                binary,
+                argument: None,
+                run_arguments: vec![],
+                insecure_argument: None,
+                imagetools: true,
+            },
+        }
+    }
+
+    /// Copy of the driver which passes the extra arguments when running a container
+    pub fn with_run_arguments(&self, run_arguments: Vec<String>) -> Self {
+        DockerCliCompatible {
+            run_arguments,
+            ..self.clone()
+        }
+    }
+
+    /// Arguments of `run`, the container is removed when it exits
+    #[allow(clippy::too_many_arguments)]
+    fn run_arguments(
+        &self,
+        repository: &str,
+        reference: &Reference,
+        name: &str,
+        mounts: Vec<Mount>,
+        entrypoint: Option<String>,
+        cmd: Option<String>,
+        args: Option<Vec<String>>,
+        env_vars: Vec<EnvVar>,
+        env_files: Vec<PathBuf>,
+        workdir: Option<PathBuf>,
+        init: Option<bool>,
+        ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        platform: Option<Platform>,
+        cap_add: Vec<String>,
+        cap_drop: Vec<String>,
+        read_only: bool,
+        privileged: bool,
+        labels: HashMap<String, String>,
+        user: Option<String>,
+        seccomp: Option<Seccomp>,
+        extra_hosts: Vec<String>,
+        networks: Vec<String>,
+        stop_signal: Option<String>,
+        stop_timeout: Option<Duration>,
+        detach: bool,
+        tty: bool,
+    ) -> Vec<OsString> {
+        let mut arguments: Vec<OsString> = vec!["run".into()];
+        arguments.extend(self.run_arguments.iter().map(OsString::from));
+        arguments.push("--rm".into());
+        if detach {
+            arguments.push("--detach".into());
+        } else {
+            arguments.push("--interactive".into());
+            if tty {
+                arguments.push("--tty".into());
+            }
+        }
+
+        arguments.push("--pull".into());
+        arguments.push("never".into());
+
+        arguments.push("--name".into());
+        arguments.push(name.into());
+
+        for env_var in env_vars {
+            arguments.push("--env".into());
+            arguments.push(format!("{}={}", env_var.name.to_uppercase(), env_var.value).into());
+        }
+
+        for env_file in env_files {
+            arguments.push("--env-file".into());
+            arguments.push(env_file.into());
+        }
+
+        for mount in mounts {
+            arguments.push("--mount".into());
+
+            if let Some(tmpfs) = &mount.tmpfs {
+                let mut arg = format!("type=tmpfs,target={}", mount.target.display());
+                if let Some(size) = &tmpfs.size {
+                    arg.push_str(format!(",tmpfs-size={}", size).as_str());
+                }
+                if let Some(mode) = tmpfs.mode {
+                    arg.push_str(format!(",tmpfs-mode={:o}", mode).as_str());
+                }
+                arguments.push(arg.into());
+                continue;
+            }
+
+            let mut arg = format!(
+                "type=bind,target={},source={}",
+                mount.target.display(),
+                mount.source.display(),
+            );
+            arg.push_str(format!(",consistency={}", mount.consistency).as_str());
+            arg.push_str(format!(",bind-propagation={}", mount.propagation).as_str());
+            arg.push_str(
+                format!(
+                    ",bind-nonrecursive={}",
+                    if mount.non_recursive.is_non_recursive() {
+                        "true"
+                    } else {
+                        "false"
+                    }
+                )
+                .as_str(),
+            );
+            arguments.push(arg.into());
+        }
+
+        if let Some(workdir) = workdir {
+            arguments.push("--workdir".into());
+            arguments.push(workdir.into());
+        }
+
+        if let Some(entrypoint) = entrypoint {
+            arguments.push("--entrypoint".into());
+            arguments.push(entrypoint.into());
+        }
+
+        if let Some(platform) = platform {
+            arguments.push("--platform".into());
+            arguments.push(platform.to_string().into());
+        }
+
+        if let Some(init) = init {
+            if init {
+                arguments.push("--init".into());
+            }
+        }
+
+        for (host, container) in ports {
+            arguments.push("-p".into());
+            arguments.push(format!("{}:{}", host, container).into());
+        }
+
+        for capability in cap_add {
+            arguments.push("--cap-add".into());
+            arguments.push(capability.into());
+        }
+
+        for capability in cap_drop {
+            arguments.push("--cap-drop".into());
+            arguments.push(capability.into());
+        }
+
+        if read_only {
+            arguments.push("--read-only".into());
+        }
+
+        if privileged {
+            arguments.push("--privileged".into());
+        }
+
+        for (key, value) in labels {
+            arguments.push("--label".into());
+            arguments.push(format!("{}={}", key, value).into());
+        }
+
+        if let Some(user) = user {
+            arguments.push("--user".into());
+            arguments.push(user.into());
+        }
+
+        match seccomp {
+            Some(Seccomp::Unconfined) => {
+                arguments.push("--security-opt".into());
+                arguments.push("seccomp=unconfined".into());
+            }
+            Some(Seccomp::Profile(path)) => {
+                arguments.push("--security-opt".into());
+                arguments.push(format!("seccomp={}", path.display()).into());
+            }
+            None => {}
+        }
+
+        for host in extra_hosts {
+            arguments.push("--add-host".into());
+            if host == "host-gateway" {
+                arguments.push("host.docker.internal:host-gateway".into());
+            } else {
+                arguments.push(host.into());
+            }
+        }
+
+        for network in networks {
+            arguments.push("--network".into());
+            arguments.push(network.into());
+        }
+
+        if let Some(stop_signal) = stop_signal {
+            arguments.push("--stop-signal".into());
+            arguments.push(stop_signal.into());
+        }
+
+        if let Some(stop_timeout) = stop_timeout {
+            arguments.push("--stop-timeout".into());
+            arguments.push(stop_timeout.as_secs().to_string().into());
+        }
+
+        if let Some(healthcheck) = healthcheck {
+            match healthcheck.test.split_first() {
+                Some((kind, _)) if kind == "NONE" => {
+                    arguments.push("--no-healthcheck".into());
+                }
+                Some((kind, test)) if kind == "CMD" => {
+                    // The CLI always runs the check through the shell of the image, quoting keeps
+                    // the arguments of the exec form apart
+                    let test: Vec<String> = test.iter().map(|arg| shell_quote(arg)).collect();
+                    arguments.push("--health-cmd".into());
+                    arguments.push(test.join(" ").into());
+                }
+                Some((kind, test)) if kind == "CMD-SHELL" => {
+                    arguments.push("--health-cmd".into());
+                    arguments.push(test.join(" ").into());
+                }
+                Some(_) => {
+                    arguments.push("--health-cmd".into());
+                    arguments.push(healthcheck.test.join(" ").into());
+                }
+                None => {}
+            }
+
+            if let Some(interval) = healthcheck.interval {
+                arguments.push("--health-interval".into());
+                arguments.push(format!("{}ms", interval.as_millis()).into());
+            }
+
+            if let Some(timeout) = healthcheck.timeout {
+                arguments.push("--health-timeout".into());
+                arguments.push(format!("{}ms", timeout.as_millis()).into());
+            }
+
+            if let Some(retries) = healthcheck.retries {
+                arguments.push("--health-retries".into());
+                arguments.push(retries.to_string().into());
+            }
+
+            if let Some(start_period) = healthcheck.start_period {
+                arguments.push("--health-start-period".into());
+                arguments.push(format!("{}ms", start_period.as_millis()).into());
+            }
+        }
+
+        match reference {
+            Reference::Digest(digest) => {
+                arguments.push(format!("{}@{}", repository, digest).into())
+            }
+            Reference::Tag(tag) => arguments.push(format!("{}:{}", repository, tag).into()),
+        };
+
+        if let Some(cmd) = cmd {
+            arguments.push(cmd.into());
+        }
+        if let Some(args) = args {
+            for arg in args {
+                arguments.push(arg.into());
+            }
+        }
+
+        arguments
+    }
+}
+
+#[async_trait]
+impl Driver for DockerCliCompatible {
+    async fn kill(&self, name: &str, signal: &str) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("kill");
+        command.arg("--signal");
+        command.arg(signal);
+        command.arg(name);
+
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run kill command")?;
+
+        if !output.status.success() {
+            bail!(
+                "kill command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn stop(&self, name: &str, timeout: Option<Duration>) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("stop");
+        if let Some(timeout) = timeout {
+            command.arg("--time");
+            command.arg(timeout.as_secs().to_string());
+        }
+        command.arg(name);
+
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run stop command")?;
+
+        if !output.status.success() {
+            bail!(
+                "stop command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn path(&self, repository: &str, reference: &Reference) -> Result<Option<String>> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+
+        command.arg("inspect");
+        command.arg("--format={{json .Config.Env}}");
+        match reference {
+            Reference::Digest(digest) => command.arg(format!("{}@{}", repository, digest)),
+            Reference::Tag(tag) => command.arg(format!("{}:{}", repository, tag)),
+        };
+
+        command.stdin(Stdio::null());
+        command.stderr(Stdio::null());
+
+        let output = command
+            .output()
+            .await
+            .context("could not run inspect command to determine path")?;
+
+        let output_utf8 = String::from_utf8_lossy(&output.stdout);
+        let regex = Regex::new(r#"PATH=([^"]+)"#).unwrap();
+        let captures = regex.captures(&output_utf8);
+        let path = captures
+            .and_then(|captures| {
+                let capture = captures.get(1);
+                capture.map(|capture| capture.as_str())
+            })
+            .map(|path| path.to_string());
+
+        Ok(path)
+    }
+
+    async fn version(&self) -> Result<String> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("version");
+        command.arg("--format={{.Server.Version}}");
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run version command")?;
+
+        if !output.status.success() {
+            bail!(
+                "version command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    async fn pull(
+        &self,
+        image: &RegistrySource,
+        platform: Option<Platform>,
+        progress: Progress,
+    ) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("pull");
+
+        if image.is_insecure() {
+            match self.insecure_argument {
+                Some(argument) => {
+                    command.arg(argument);
+                }
+                None => log::warn!(
+                    "registry `{}` is insecure, it must be listed in the `insecure-registries` of the daemon",
+                    image.host()
+                ),
+            }
+        }
+
+        if let Some(platform) = platform {
+            command.arg("--platform");
+            command.arg(platform.to_string());
+        }
+
+        command.arg(format!("{}", image));
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let (status, errors) = progress::report(&mut command, progress)
+            .await
+            .context("could not run pull command")?;
+
+        if !status.success() {
+            bail!("pull command failed: {}", errors.trim());
+        }
+
+        Ok(())
+    }
+
+    async fn network_exists(&self, name: &str) -> Result<bool> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("network");
+        command.arg("inspect");
+        command.arg(name);
+
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::null());
+        command.stderr(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let status = command
+            .status()
+            .await
+            .context("could not run network inspect command")?;
+
+        Ok(status.success())
+    }
+
+    async fn create_network(
+        &self,
+        name: &str,
+        driver: &str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("network");
+        command.arg("create");
+        command.arg("--driver");
+        command.arg(driver);
+        if internal {
+            command.arg("--internal");
+        }
+        for (key, value) in labels {
+            command.arg("--label");
+            command.arg(format!("{}={}", key, value));
+        }
+        command.arg(name);
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run network create command")?;
+
+        if !output.status.success() {
+            bail!(
+                "network create command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn networks(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("network");
+        command.arg("ls");
+        command.arg("--filter");
+        command.arg(format!("label={}={}", label.0, label.1));
+        command.arg("--format={{.Name}}");
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run network ls command")?;
+
+        if !output.status.success() {
+            bail!(
+                "network ls command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn remove_network(&self, name: &str) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("network");
+        command.arg("rm");
+        command.arg(name);
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run network rm command")?;
+
+        if !output.status.success() {
+            bail!(
+                "network rm command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn containers(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("ps");
+        command.arg("--all");
+        command.arg("--filter");
+        command.arg(format!("label={}={}", label.0, label.1));
+        command.arg("--format={{.ID}}");
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command.output().await.context("could not run ps command")?;
+
+        if !output.status.success() {
+            bail!(
+                "ps command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn remove_container(&self, id: &str) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("rm");
+        command.arg("--force");
+        command.arg(id);
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command.output().await.context("could not run rm command")?;
+
+        if !output.status.success() {
+            bail!(
+                "rm command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn login(&self, registry: &str) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("login");
+        command.arg(registry);
 
-        let (client, binary) =
-            first_supported.ok_or_else(|| anyhow!("No supported driver installed in $PATH"))?;
-        log::info!("using client `{}`", client);
+        log::trace!("{:#?}", command);
 
-        Ok(match client {
-            "colima" => DockerCliCompatible {
-                binary: binary.unwrap(),
-                argument: Some("nerdctl".into()),
-            },
-            "lima" => DockerCliCompatible {
-                binary: binary.unwrap(),
-                argument: Some("nerdctl".into()),
-            },
-            _ => DockerCliCompatible {
-                binary: binary.unwrap(),
-                argument: None,
-            },
-        })
+        // Inherit stdio so the runtime can prompt for the credentials and store them
+        let status = command
+            .status()
+            .await
+            .context("could not run login command")?;
+
+        if !status.success() {
+            bail!("login command failed");
+        }
+
+        Ok(())
     }
-}
 
-// TODO remove impl as resolve_with_supported_binary is fallible
-impl Default for DockerCliCompatible {
-    fn default() -> Self {
-        DockerCliCompatible::resolve_with_supported_binary().unwrap()
+    async fn logout(&self, registry: &str) -> Result<()> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("logout");
+        command.arg(registry);
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command
+            .output()
+            .await
+            .context("could not run logout command")?;
+
+        if !output.status.success() {
+            bail!(
+                "logout command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        Ok(())
     }
-}
 
-#[async_trait]
-impl Driver for DockerCliCompatible {
-    async fn path(&self, repository: &str, reference: &Reference) -> Result<Option<String>> {
+    async fn list(&self, all: bool, labels: &[(&str, &str)]) -> Result<Vec<RunningContainer>> {
         let mut command = Command::new(&self.binary);
         if let Some(argument) = &self.argument {
             command.arg(argument);
         }
+        command.arg("ps");
+        if all {
+            command.arg("--all");
+        }
+        for (key, value) in labels {
+            command.arg("--filter");
+            command.arg(format!("label={}={}", key, value));
+        }
+        command.arg("--format={{json .}}");
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
+        let output = command.output().await.context("could not run ps command")?;
+
+        if !output.status.success() {
+            bail!(
+                "ps command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        parse_ps(&String::from_utf8_lossy(&output.stdout))
+    }
 
+    async fn exists(&self, repository: &str, reference: &Reference) -> Result<bool> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("image");
         command.arg("inspect");
-        command.arg("--format={{json .Config.Env}}");
         match reference {
             Reference::Digest(digest) => command.arg(format!("{}@{}", repository, digest)),
             Reference::Tag(tag) => command.arg(format!("{}:{}", repository, tag)),
         };
 
         command.stdin(Stdio::null());
+        command.stdout(Stdio::null());
         command.stderr(Stdio::null());
 
+        log::trace!("{:#?}", command);
+
+        let status = command
+            .status()
+            .await
+            .context("could not run image inspect command")?;
+
+        Ok(status.success())
+    }
+
+    async fn digest(&self, repository: &str, reference: &Reference) -> Result<Option<Digest>> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("image");
+        command.arg("inspect");
+        command.arg("--format={{json .RepoDigests}}");
+        match reference {
+            Reference::Digest(digest) => command.arg(format!("{}@{}", repository, digest)),
+            Reference::Tag(tag) => command.arg(format!("{}:{}", repository, tag)),
+        };
+
+        command.stdin(Stdio::null());
+
+        log::trace!("{:#?}", command);
+
         let output = command
             .output()
             .await
-            .context("could not run inspect command to determine path")?;
+            .context("could not run image inspect command")?;
 
-        let output_utf8 = String::from_utf8_lossy(&output.stdout);
-        let regex = Regex::new(r#"PATH=([^"]+)"#).unwrap();
-        let captures = regex.captures(&output_utf8);
-        let path = captures
-            .and_then(|captures| {
-                let capture = captures.get(1);
-                capture.map(|capture| capture.as_str())
+        if !output.status.success() {
+            bail!(
+                "image inspect command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        // Locally built images do not have a repository digest
+        let repo_digests: Option<Vec<String>> =
+            serde_json::from_slice(&output.stdout).context("could not parse repository digests")?;
+        let digest = repo_digests
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|repo_digest| repo_digest.split_once('@'))
+            .find(|(name, _)| {
+                *name == repository
+                    || name.ends_with(&format!("/{}", repository))
+                    || repository.ends_with(&format!("/{}", name))
             })
-            .map(|path| path.to_string());
+            .map(|(_, digest)| Digest::try_from(digest))
+            .transpose()?;
 
-        Ok(path)
+        Ok(digest)
     }
 
-    async fn pull(&self, image: &RegistrySource) -> Result<()> {
+    async fn remote_digest(&self, image: &RegistrySource) -> Result<Digest> {
+        if !self.imagetools {
+            bail!(
+                "`{}` cannot inspect remote images, only docker with buildx can",
+                self.binary.display()
+            );
+        }
+
         let mut command = Command::new(&self.binary);
         if let Some(argument) = &self.argument {
             command.arg(argument);
         }
-        command.arg("pull");
+        command.arg("buildx");
+        command.arg("imagetools");
+        command.arg("inspect");
+        command.arg("--format={{json .Manifest}}");
         command.arg(format!("{}", image));
 
         command.stdin(Stdio::null());
-        command.stdout(Stdio::null());
-        command.stderr(Stdio::null());
 
         log::trace!("{:#?}", command);
 
         let output = command
             .output()
             .await
-            .context("could not run pull command")?;
+            .context("could not run imagetools inspect command")?;
 
         if !output.status.success() {
-            println!("{}", String::from_utf8_lossy(&output.stderr));
-            bail!("prepare command failed");
+            bail!(
+                "imagetools inspect command failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
         }
 
-        Ok(())
+        #[derive(Deserialize)]
+        struct Manifest {
+            digest: String,
+        }
+
+        let manifest: Manifest =
+            serde_json::from_slice(&output.stdout).context("could not parse remote manifest")?;
+
+        Digest::try_from(manifest.digest.as_str())
+    }
+
+    async fn exec(
+        &self,
+        id: &str,
+        workdir: Option<PathBuf>,
+        exec_command: Vec<String>,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("exec");
+        command.arg("--interactive");
+        if tty {
+            command.arg("--tty");
+        }
+
+        if let Some(workdir) = workdir {
+            command.arg("--workdir");
+            command.arg(workdir);
+        }
+
+        command.arg(id);
+        command.args(exec_command);
+
+        log::trace!("{:#?}", command);
+        let status = command
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .context("could not start exec command")?
+            .wait()
+            .await
+            .context("could not run exec command")?;
+
+        Ok(status)
+    }
+
+    async fn logs(
+        &self,
+        id: &str,
+        follow: bool,
+        tail: Option<u32>,
+        since: Option<Duration>,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        let mut command = Command::new(&self.binary);
+        if let Some(argument) = &self.argument {
+            command.arg(argument);
+        }
+        command.arg("logs");
+
+        if follow {
+            command.arg("--follow");
+        }
+
+        if let Some(tail) = tail {
+            command.arg("--tail");
+            command.arg(tail.to_string());
+        }
+
+        if let Some(since) = since {
+            // Relative to now, so there is no need to compute a timestamp
+            command.arg("--since");
+            command.arg(format!("{}s", since.as_secs()));
+        }
+
+        command.arg(id);
+
+        log::trace!("{:#?}", command);
+        let status = command
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .context("could not start logs command")?
+            .wait()
+            .await
+            .context("could not run logs command")?;
+
+        Ok(status)
     }
 
     async fn build<C, F>(
@@ -133,8 +1075,12 @@ impl Driver for DockerCliCompatible {
         secrets: Vec<Secret>,
         ssh_sockets: Vec<Ssh>,
         target: Option<String>,
+        platform: Option<Platform>,
+        labels: HashMap<String, String>,
         repository: &str,
         reference: &Reference,
+        no_cache: bool,
+        progress: Progress,
     ) -> Result<()>
     where
         C: AsRef<Path> + Send,
@@ -174,27 +1120,47 @@ impl Driver for DockerCliCompatible {
             command.arg(target);
         }
 
+        if let Some(platform) = platform {
+            command.arg("--platform");
+            command.arg(platform.to_string());
+        }
+
+        for (key, value) in labels {
+            command.arg("--label");
+            command.arg(format!("{}={}", key, value));
+        }
+
         command.arg("--tag");
         match reference {
             Reference::Digest(digest) => command.arg(format!("{}@{}", repository, digest)),
             Reference::Tag(tag) => command.arg(format!("{}:{}", repository, tag)),
         };
 
-        command.arg("--quiet");
+        if no_cache {
+            command.arg("--no-cache");
+        }
+
+        if progress == Progress::None {
+            command.arg("--quiet");
+        } else {
+            // Plain BuildKit output contains the timing of each step
+            command.arg("--progress=plain");
+        }
         command.arg(context.as_ref());
         command.stdin(Stdio::null());
-        command.stderr(Stdio::null());
 
         log::trace!("{:#?}", command);
 
-        let output = command
-            .output()
+        let (status, output) = progress::report(&mut command, progress)
             .await
             .context("could not run prepare command")?;
 
-        if !output.status.success() {
-            println!("{}", String::from_utf8_lossy(&output.stderr));
-            bail!("prepare command failed");
+        if !status.success() {
+            bail!(
+                "could not build image `{}`:\n{}",
+                repository,
+                build_errors(&output)
+            );
         }
 
         Ok(())
@@ -204,6 +1170,7 @@ impl Driver for DockerCliCompatible {
         &self,
         repository: &str,
         reference: &Reference,
+        name: &str,
         mounts: Vec<Mount>,
         entrypoint: Option<String>,
         cmd: Option<String>,
@@ -213,98 +1180,290 @@ impl Driver for DockerCliCompatible {
         workdir: Option<PathBuf>,
         init: Option<bool>,
         ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        platform: Option<Platform>,
+        cap_add: Vec<String>,
+        cap_drop: Vec<String>,
+        read_only: bool,
+        privileged: bool,
+        labels: HashMap<String, String>,
+        user: Option<String>,
+        seccomp: Option<Seccomp>,
+        extra_hosts: Vec<String>,
+        networks: Vec<String>,
+        stop_signal: Option<String>,
+        stop_timeout: Option<Duration>,
+        detach: bool,
+        tty: bool,
         stdin: Stdio,
         stdout: Stdio,
         stderr: Stdio,
-    ) -> Result<()> {
+    ) -> Result<ExitStatus> {
         let mut command = Command::new(&self.binary);
-        command.arg("run");
-        command.arg("--rm");
-        command.arg("-it");
+        command.args(self.run_arguments(
+            repository,
+            reference,
+            name,
+            mounts,
+            entrypoint,
+            cmd,
+            args,
+            env_vars,
+            env_files,
+            workdir,
+            init,
+            ports,
+            healthcheck,
+            platform,
+            cap_add,
+            cap_drop,
+            read_only,
+            privileged,
+            labels,
+            user,
+            seccomp,
+            extra_hosts,
+            networks,
+            stop_signal,
+            stop_timeout,
+            detach,
+            tty,
+        ));
 
-        command.arg("--pull");
-        command.arg("never");
+        log::trace!("{:#?}", command);
+        let status = command
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .context("could not start run command")?
+            .wait()
+            .await
+            .context("could not run run command")?;
 
-        for env_var in env_vars {
-            command.arg("--env");
-            command.arg(format!("{}={}", env_var.name.to_uppercase(), env_var.value));
-        }
+        Ok(status)
+    }
+}
 
-        for env_file in env_files {
-            command.arg("--env-file");
-            command.arg(env_file);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Tmpfs;
 
-        for mount in mounts {
-            command.arg("--mount");
+    /// Options of a run, only the ones under test differ from the defaults
+    #[derive(Default)]
+    struct Run {
+        reference: Reference,
+        mounts: Vec<Mount>,
+        env_files: Vec<PathBuf>,
+        init: Option<bool>,
+        ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        cap_add: Vec<String>,
+        read_only: bool,
+        labels: HashMap<String, String>,
+        extra_hosts: Vec<String>,
+        detach: bool,
+        tty: bool,
+    }
 
-            let mut arg = format!(
-                "type=bind,target={},source={}",
-                mount.target.display(),
-                mount.source.display(),
-            );
-            arg.push_str(format!(",consistency={}", mount.consistency).as_str());
-            arg.push_str(format!(",bind-propagation={}", mount.propagation).as_str());
-            arg.push_str(
-                format!(
-                    ",bind-nonrecursive={}",
-                    if mount.non_recursive.is_non_recursive() {
-                        "true"
-                    } else {
-                        "false"
-                    }
-                )
-                .as_str(),
-            );
-            command.arg(arg);
-        }
+    fn arguments(run: Run) -> Vec<String> {
+        DockerCliCompatible::new("docker", PathBuf::from("docker"))
+            .run_arguments(
+                "tool",
+                &run.reference,
+                "toip-tool",
+                run.mounts,
+                None,
+                None,
+                None,
+                vec![],
+                run.env_files,
+                None,
+                run.init,
+                run.ports,
+                run.healthcheck,
+                None,
+                run.cap_add,
+                vec![],
+                run.read_only,
+                false,
+                run.labels,
+                None,
+                None,
+                run.extra_hosts,
+                vec![],
+                None,
+                None,
+                run.detach,
+                run.tty,
+            )
+            .into_iter()
+            .map(|argument| argument.into_string().unwrap())
+            .collect()
+    }
 
-        if let Some(workdir) = workdir {
-            command.arg("--workdir");
-            command.arg(workdir);
-        }
+    /// Whether the flag is passed with the value
+    fn passes(arguments: &[String], flag: &str, value: &str) -> bool {
+        arguments
+            .windows(2)
+            .any(|pair| pair[0] == flag && pair[1] == value)
+    }
 
-        if let Some(entrypoint) = entrypoint {
-            command.arg("--entrypoint");
-            command.arg(entrypoint);
-        }
+    #[test]
+    fn emits_container_options() {
+        let arguments = arguments(Run {
+            mounts: vec![Mount {
+                source: PathBuf::new(),
+                consistency: Default::default(),
+                propagation: Default::default(),
+                non_recursive: Default::default(),
+                tmpfs: Some(Tmpfs {
+                    size: Some("64m".into()),
+                    mode: Some(0o1777),
+                }),
+                target: PathBuf::from("/tmp"),
+                readonly: false,
+            }],
+            env_files: vec![PathBuf::from("/project/.env")],
+            init: Some(true),
+            ports: vec![(8080, 80)].into_iter().collect(),
+            cap_add: vec!["NET_ADMIN".into()],
+            read_only: true,
+            labels: vec![("toip.container.name".into(), "tool".into())]
+                .into_iter()
+                .collect(),
+            extra_hosts: vec!["host-gateway".into(), "db:10.0.0.2".into()],
+            ..Default::default()
+        });
 
-        if let Some(init) = init {
-            if init {
-                command.arg("--init");
-            }
-        }
+        assert!(passes(&arguments, "-p", "8080:80"));
+        assert!(passes(&arguments, "--cap-add", "NET_ADMIN"));
+        assert!(passes(
+            &arguments,
+            "--mount",
+            "type=tmpfs,target=/tmp,tmpfs-size=64m,tmpfs-mode=1777"
+        ));
+        assert!(arguments.contains(&"--read-only".into()));
+        assert!(arguments.contains(&"--init".into()));
+        assert!(passes(&arguments, "--label", "toip.container.name=tool"));
+        assert!(passes(&arguments, "--env-file", "/project/.env"));
+        assert!(passes(
+            &arguments,
+            "--add-host",
+            "host.docker.internal:host-gateway"
+        ));
+        assert!(passes(&arguments, "--add-host", "db:10.0.0.2"));
+        assert_eq!(arguments.last().unwrap(), "tool:latest");
+    }
 
-        for (host, container) in ports {
-            command.arg("-p");
-            command.arg(format!("{}:{}", host, container));
-        }
+    #[test]
+    fn runs_locked_digest() {
+        let arguments = arguments(Run {
+            reference: Reference::Digest(Digest::try_from("sha256:aaaa").unwrap()),
+            ..Default::default()
+        });
 
-        match reference {
-            Reference::Digest(digest) => command.arg(format!("{}@{}", repository, digest)),
-            Reference::Tag(tag) => command.arg(format!("{}:{}", repository, tag)),
-        };
+        assert_eq!(arguments.last().unwrap(), "tool@sha256:aaaa");
+    }
 
-        if let Some(cmd) = cmd {
-            command.arg(cmd);
-        }
-        if let Some(args) = args {
-            for arg in args {
-                command.arg(arg);
-            }
+    #[test]
+    fn omits_unset_options() {
+        let arguments = arguments(Run {
+            init: Some(false),
+            ..Default::default()
+        });
+
+        for flag in &[
+            "-p",
+            "--cap-add",
+            "--mount",
+            "--read-only",
+            "--init",
+            "--label",
+        ] {
+            assert!(!arguments.contains(&flag.to_string()), "{}", flag);
         }
+        assert!(arguments.contains(&"--interactive".into()));
+        assert!(!arguments.contains(&"--tty".into()));
+    }
 
-        log::trace!("{:#?}", command);
-        command
-            .stdin(stdin)
-            .stdout(stdout)
-            .stderr(stderr)
-            .spawn()
-            .context("could not start run command")?
-            .wait()
-            .await
-            .context("could not run run command")?;
+    #[test]
+    fn detached_runs_are_not_interactive() {
+        let arguments = arguments(Run {
+            detach: true,
+            tty: true,
+            ..Default::default()
+        });
 
-        Ok(())
+        assert!(arguments.contains(&"--detach".into()));
+        assert!(!arguments.contains(&"--interactive".into()));
+        assert!(!arguments.contains(&"--tty".into()));
+    }
+
+    #[test]
+    fn emits_healthcheck_options() {
+        let arguments = arguments(Run {
+            healthcheck: Some(HealthCheck {
+                test: vec!["CMD-SHELL".into(), "curl -f http://localhost".into()],
+                interval: Some(Duration::from_secs(30)),
+                timeout: Some(Duration::from_secs(5)),
+                retries: Some(3),
+                start_period: Some(Duration::from_millis(1500)),
+            }),
+            ..Default::default()
+        });
+
+        assert!(passes(
+            &arguments,
+            "--health-cmd",
+            "curl -f http://localhost"
+        ));
+        assert!(passes(&arguments, "--health-interval", "30000ms"));
+        assert!(passes(&arguments, "--health-timeout", "5000ms"));
+        assert!(passes(&arguments, "--health-retries", "3"));
+        assert!(passes(&arguments, "--health-start-period", "1500ms"));
+    }
+
+    #[test]
+    fn keeps_exec_form_healthcheck_arguments_apart() {
+        let arguments = arguments(Run {
+            healthcheck: Some(HealthCheck {
+                test: vec![
+                    "CMD".into(),
+                    "curl".into(),
+                    "-f".into(),
+                    "http://localhost/it's up".into(),
+                ],
+                interval: None,
+                timeout: None,
+                retries: None,
+                start_period: None,
+            }),
+            ..Default::default()
+        });
+
+        assert!(passes(
+            &arguments,
+            "--health-cmd",
+            "'curl' '-f' 'http://localhost/it'\\''s up'"
+        ));
+    }
+
+    #[test]
+    fn disables_healthcheck() {
+        let arguments = arguments(Run {
+            healthcheck: Some(HealthCheck {
+                test: vec!["NONE".into()],
+                interval: None,
+                timeout: None,
+                retries: None,
+                start_period: None,
+            }),
+            ..Default::default()
+        });
+
+        assert!(arguments.contains(&"--no-healthcheck".into()));
+        assert!(!arguments.contains(&"--health-cmd".into()));
     }
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tokio::sync::Notify;
+
+use crate::backend::progress::Progress;
+use crate::backend::{BuildArg, Driver, EnvVar, Mount, RunningContainer, Seccomp, Secret, Ssh};
+use crate::config::{Digest, HealthCheck, Platform, Reference, RegistrySource};
+
+/// Records the calls made by the backend, so it can be tested without a container runtime
+#[derive(Default)]
+pub struct MockDriver {
+    /// Digests served by the registry, by image reference
+    pub remote_digests: HashMap<String, Digest>,
+    pub pulled: Arc<Mutex<Vec<String>>>,
+    pub killed: Arc<Mutex<Vec<(String, String)>>>,
+    /// Notified for every signal, so a fake container can exit on it
+    pub stopped: Arc<Notify>,
+}
+
+#[async_trait]
+impl Driver for MockDriver {
+    async fn kill(&self, name: &str, signal: &str) -> Result<()> {
+        self.killed
+            .lock()
+            .unwrap()
+            .push((name.to_string(), signal.to_string()));
+        self.stopped.notify_one();
+        Ok(())
+    }
+
+    async fn stop(&self, _name: &str, _timeout: Option<Duration>) -> Result<()> {
+        unimplemented!("stop is not mocked")
+    }
+
+    async fn path(&self, _repository: &str, _reference: &Reference) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn version(&self) -> Result<String> {
+        unimplemented!("version is not mocked")
+    }
+
+    async fn pull(
+        &self,
+        image: &RegistrySource,
+        _platform: Option<Platform>,
+        _progress: Progress,
+    ) -> Result<()> {
+        self.pulled.lock().unwrap().push(image.to_string());
+        Ok(())
+    }
+
+    async fn network_exists(&self, _name: &str) -> Result<bool> {
+        unimplemented!("network_exists is not mocked")
+    }
+
+    async fn create_network(
+        &self,
+        _name: &str,
+        _driver: &str,
+        _internal: bool,
+        _labels: HashMap<String, String>,
+    ) -> Result<()> {
+        unimplemented!("create_network is not mocked")
+    }
+
+    async fn networks(&self, _label: (&str, &str)) -> Result<Vec<String>> {
+        unimplemented!("networks is not mocked")
+    }
+
+    async fn remove_network(&self, _name: &str) -> Result<()> {
+        unimplemented!("remove_network is not mocked")
+    }
+
+    async fn containers(&self, _label: (&str, &str)) -> Result<Vec<String>> {
+        unimplemented!("containers is not mocked")
+    }
+
+    async fn remove_container(&self, _id: &str) -> Result<()> {
+        unimplemented!("remove_container is not mocked")
+    }
+
+    async fn login(&self, _registry: &str) -> Result<()> {
+        unimplemented!("login is not mocked")
+    }
+
+    async fn logout(&self, _registry: &str) -> Result<()> {
+        unimplemented!("logout is not mocked")
+    }
+
+    async fn list(&self, _all: bool, _labels: &[(&str, &str)]) -> Result<Vec<RunningContainer>> {
+        unimplemented!("list is not mocked")
+    }
+
+    async fn exists(&self, _repository: &str, _reference: &Reference) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn digest(&self, _repository: &str, _reference: &Reference) -> Result<Option<Digest>> {
+        unimplemented!("digest is not mocked")
+    }
+
+    async fn remote_digest(&self, image: &RegistrySource) -> Result<Digest> {
+        self.remote_digests
+            .get(&image.to_string())
+            .cloned()
+            .ok_or_else(|| anyhow!("manifest unknown"))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn exec(
+        &self,
+        _id: &str,
+        _workdir: Option<PathBuf>,
+        _command: Vec<String>,
+        _tty: bool,
+        _stdin: Stdio,
+        _stdout: Stdio,
+        _stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        unimplemented!("exec is not mocked")
+    }
+
+    async fn logs(
+        &self,
+        _id: &str,
+        _follow: bool,
+        _tail: Option<u32>,
+        _since: Option<Duration>,
+        _stdout: Stdio,
+        _stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        unimplemented!("logs is not mocked")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn build<C, F>(
+        &self,
+        _context: C,
+        _file: F,
+        _build_args: Vec<BuildArg>,
+        _secrets: Vec<Secret>,
+        _ssh_sockets: Vec<Ssh>,
+        _target: Option<String>,
+        _platform: Option<Platform>,
+        _labels: HashMap<String, String>,
+        _repository: &str,
+        _reference: &Reference,
+        _no_cache: bool,
+        _progress: Progress,
+    ) -> Result<()>
+    where
+        C: AsRef<Path> + Send,
+        F: AsRef<Path> + Send,
+    {
+        unimplemented!("build is not mocked")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run(
+        &self,
+        _repository: &str,
+        _reference: &Reference,
+        _name: &str,
+        _mounts: Vec<Mount>,
+        _entrypoint: Option<String>,
+        _cmd: Option<String>,
+        _args: Option<Vec<String>>,
+        _env_vars: Vec<EnvVar>,
+        _env_files: Vec<PathBuf>,
+        _workdir: Option<PathBuf>,
+        _init: Option<bool>,
+        _ports: HashMap<u16, u16>,
+        _healthcheck: Option<HealthCheck>,
+        _platform: Option<Platform>,
+        _cap_add: Vec<String>,
+        _cap_drop: Vec<String>,
+        _read_only: bool,
+        _privileged: bool,
+        _labels: HashMap<String, String>,
+        _user: Option<String>,
+        _seccomp: Option<Seccomp>,
+        _extra_hosts: Vec<String>,
+        _networks: Vec<String>,
+        _stop_signal: Option<String>,
+        _stop_timeout: Option<Duration>,
+        _detach: bool,
+        _tty: bool,
+        _stdin: Stdio,
+        _stdout: Stdio,
+        _stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        unimplemented!("run is not mocked")
+    }
+}
@@ -1,23 +1,99 @@
 mod docker;
+#[cfg(test)]
+pub mod mock;
+mod podman;
 
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
 
-use anyhow::Result;
+use std::env;
+
+use anyhow::{anyhow, bail, Result};
 use async_trait::async_trait;
 pub use docker::DockerCliCompatible;
+pub use podman::PodmanDriver;
+use which::which;
+
+use crate::backend::progress::Progress;
+use crate::backend::{BuildArg, EnvVar, Mount, RunningContainer, Seccomp, Secret, Ssh};
+use crate::config::{Digest, HealthCheck, Platform, Reference, RegistrySource};
 
-use crate::backend::{BuildArg, EnvVar, Mount, Secret, Ssh};
-use crate::config::{Reference, RegistrySource};
+/// Supported container runtimes, in order of preference
+pub const DRIVERS: [&str; 5] = ["colima", "lima", "nerdctl", "docker", "podman"];
+pub const DRIVER_VARIABLE: &str = "TOIP_DRIVER";
 
 #[async_trait]
 pub trait Driver {
+    async fn kill(&self, name: &str, signal: &str) -> Result<()>;
+
+    async fn stop(&self, name: &str, timeout: Option<Duration>) -> Result<()>;
+
     async fn path(&self, _repository: &str, _reference: &Reference) -> Result<Option<String>> {
         Ok(None)
     }
 
-    async fn pull(&self, image: &RegistrySource) -> Result<()>;
+    async fn version(&self) -> Result<String>;
+
+    async fn pull(
+        &self,
+        image: &RegistrySource,
+        platform: Option<Platform>,
+        progress: Progress,
+    ) -> Result<()>;
+
+    async fn network_exists(&self, name: &str) -> Result<bool>;
+
+    async fn create_network(
+        &self,
+        name: &str,
+        driver: &str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> Result<()>;
+
+    async fn networks(&self, label: (&str, &str)) -> Result<Vec<String>>;
+
+    async fn remove_network(&self, name: &str) -> Result<()>;
+
+    async fn containers(&self, label: (&str, &str)) -> Result<Vec<String>>;
+
+    async fn remove_container(&self, id: &str) -> Result<()>;
+
+    async fn login(&self, registry: &str) -> Result<()>;
+
+    async fn logout(&self, registry: &str) -> Result<()>;
+
+    async fn list(&self, all: bool, labels: &[(&str, &str)]) -> Result<Vec<RunningContainer>>;
+
+    async fn exists(&self, repository: &str, reference: &Reference) -> Result<bool>;
+
+    async fn digest(&self, repository: &str, reference: &Reference) -> Result<Option<Digest>>;
+
+    async fn remote_digest(&self, image: &RegistrySource) -> Result<Digest>;
+
+    #[allow(clippy::too_many_arguments)]
+    async fn exec(
+        &self,
+        id: &str,
+        workdir: Option<PathBuf>,
+        command: Vec<String>,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus>;
+
+    async fn logs(
+        &self,
+        id: &str,
+        follow: bool,
+        tail: Option<u32>,
+        since: Option<Duration>,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus>;
 
     #[allow(clippy::too_many_arguments)]
     async fn build<C, F>(
@@ -28,8 +104,12 @@ pub trait Driver {
         secrets: Vec<Secret>,
         ssh_sockets: Vec<Ssh>,
         target: Option<String>,
+        platform: Option<Platform>,
+        labels: HashMap<String, String>,
         repository: &str,
         reference: &Reference,
+        no_cache: bool,
+        progress: Progress,
     ) -> Result<()>
     where
         C: AsRef<Path> + Send,
@@ -40,6 +120,7 @@ pub trait Driver {
         &self,
         repository: &str,
         reference: &Reference,
+        name: &str,
         mounts: Vec<Mount>,
         entrypoint: Option<String>,
         cmd: Option<String>,
@@ -49,9 +130,424 @@ pub trait Driver {
         workdir: Option<PathBuf>,
         init: Option<bool>,
         ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        platform: Option<Platform>,
+        cap_add: Vec<String>,
+        cap_drop: Vec<String>,
+        read_only: bool,
+        privileged: bool,
+        labels: HashMap<String, String>,
+        user: Option<String>,
+        seccomp: Option<Seccomp>,
+        extra_hosts: Vec<String>,
+        networks: Vec<String>,
+        stop_signal: Option<String>,
+        stop_timeout: Option<Duration>,
+        detach: bool,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus>;
+}
+
+/// Driver of the container runtime which is installed
+pub enum SupportedDriver {
+    DockerCliCompatible(DockerCliCompatible),
+    Podman(PodmanDriver),
+}
+
+impl SupportedDriver {
+    pub fn binary(&self) -> &Path {
+        match self {
+            SupportedDriver::DockerCliCompatible(driver) => driver.binary(),
+            SupportedDriver::Podman(driver) => driver.binary(),
+        }
+    }
+
+    pub fn resolve_with_supported_binary() -> Result<Self> {
+        // TODO, make this more robust
+        // Should also configure docker's context (where applicable)
+        let mut clients = DRIVERS.to_vec();
+        if let Ok(driver) = env::var(DRIVER_VARIABLE) {
+            if !clients.contains(&driver.as_str()) {
+                bail!(
+                    "unsupported driver `{}`, expected one of `{}`",
+                    driver,
+                    clients.join("`, `")
+                );
+            }
+            clients.retain(|client| *client == driver);
+        }
+        let first_supported = clients
+            .into_iter()
+            .map(|client| (client, which(client)))
+            .find(|(_client, binary)| binary.is_ok());
+
+        let (client, binary) =
+            first_supported.ok_or_else(|| anyhow!("No supported driver installed in $PATH"))?;
+        log::info!("using client `{}`", client);
+
+        let driver = DockerCliCompatible::new(client, binary.unwrap());
+        Ok(match client {
+            "podman" => SupportedDriver::Podman(PodmanDriver::new(driver)),
+            _ => SupportedDriver::DockerCliCompatible(driver),
+        })
+    }
+}
+
+#[async_trait]
+impl Driver for SupportedDriver {
+    async fn kill(&self, name: &str, signal: &str) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.kill(name, signal).await,
+            SupportedDriver::Podman(inner) => inner.kill(name, signal).await,
+        }
+    }
+
+    async fn stop(&self, name: &str, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.stop(name, timeout).await,
+            SupportedDriver::Podman(inner) => inner.stop(name, timeout).await,
+        }
+    }
+
+    async fn path(&self, repository: &str, reference: &Reference) -> Result<Option<String>> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.path(repository, reference).await,
+            SupportedDriver::Podman(inner) => inner.path(repository, reference).await,
+        }
+    }
+
+    async fn version(&self) -> Result<String> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.version().await,
+            SupportedDriver::Podman(inner) => inner.version().await,
+        }
+    }
+
+    async fn pull(
+        &self,
+        image: &RegistrySource,
+        platform: Option<Platform>,
+        progress: Progress,
+    ) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner.pull(image, platform, progress).await
+            }
+            SupportedDriver::Podman(inner) => inner.pull(image, platform, progress).await,
+        }
+    }
+
+    async fn network_exists(&self, name: &str) -> Result<bool> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.network_exists(name).await,
+            SupportedDriver::Podman(inner) => inner.network_exists(name).await,
+        }
+    }
+
+    async fn create_network(
+        &self,
+        name: &str,
+        driver: &str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner.create_network(name, driver, internal, labels).await
+            }
+            SupportedDriver::Podman(inner) => {
+                inner.create_network(name, driver, internal, labels).await
+            }
+        }
+    }
+
+    async fn networks(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.networks(label).await,
+            SupportedDriver::Podman(inner) => inner.networks(label).await,
+        }
+    }
+
+    async fn remove_network(&self, name: &str) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.remove_network(name).await,
+            SupportedDriver::Podman(inner) => inner.remove_network(name).await,
+        }
+    }
+
+    async fn containers(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.containers(label).await,
+            SupportedDriver::Podman(inner) => inner.containers(label).await,
+        }
+    }
+
+    async fn remove_container(&self, id: &str) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.remove_container(id).await,
+            SupportedDriver::Podman(inner) => inner.remove_container(id).await,
+        }
+    }
+
+    async fn login(&self, registry: &str) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.login(registry).await,
+            SupportedDriver::Podman(inner) => inner.login(registry).await,
+        }
+    }
+
+    async fn logout(&self, registry: &str) -> Result<()> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.logout(registry).await,
+            SupportedDriver::Podman(inner) => inner.logout(registry).await,
+        }
+    }
+
+    async fn list(&self, all: bool, labels: &[(&str, &str)]) -> Result<Vec<RunningContainer>> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.list(all, labels).await,
+            SupportedDriver::Podman(inner) => inner.list(all, labels).await,
+        }
+    }
+
+    async fn exists(&self, repository: &str, reference: &Reference) -> Result<bool> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner.exists(repository, reference).await
+            }
+            SupportedDriver::Podman(inner) => inner.exists(repository, reference).await,
+        }
+    }
+
+    async fn digest(&self, repository: &str, reference: &Reference) -> Result<Option<Digest>> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner.digest(repository, reference).await
+            }
+            SupportedDriver::Podman(inner) => inner.digest(repository, reference).await,
+        }
+    }
+
+    async fn remote_digest(&self, image: &RegistrySource) -> Result<Digest> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => inner.remote_digest(image).await,
+            SupportedDriver::Podman(inner) => inner.remote_digest(image).await,
+        }
+    }
 
+    async fn exec(
+        &self,
+        id: &str,
+        workdir: Option<PathBuf>,
+        command: Vec<String>,
+        tty: bool,
         stdin: Stdio,
         stdout: Stdio,
         stderr: Stdio,
-    ) -> Result<()>;
+    ) -> Result<ExitStatus> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner
+                    .exec(id, workdir, command, tty, stdin, stdout, stderr)
+                    .await
+            }
+            SupportedDriver::Podman(inner) => {
+                inner
+                    .exec(id, workdir, command, tty, stdin, stdout, stderr)
+                    .await
+            }
+        }
+    }
+
+    async fn logs(
+        &self,
+        id: &str,
+        follow: bool,
+        tail: Option<u32>,
+        since: Option<Duration>,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner.logs(id, follow, tail, since, stdout, stderr).await
+            }
+            SupportedDriver::Podman(inner) => {
+                inner.logs(id, follow, tail, since, stdout, stderr).await
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn build<C, F>(
+        &self,
+        context: C,
+        file: F,
+        build_args: Vec<BuildArg>,
+        secrets: Vec<Secret>,
+        ssh_sockets: Vec<Ssh>,
+        target: Option<String>,
+        platform: Option<Platform>,
+        labels: HashMap<String, String>,
+        repository: &str,
+        reference: &Reference,
+        no_cache: bool,
+        progress: Progress,
+    ) -> Result<()>
+    where
+        C: AsRef<Path> + Send,
+        F: AsRef<Path> + Send,
+    {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner
+                    .build(
+                        context,
+                        file,
+                        build_args,
+                        secrets,
+                        ssh_sockets,
+                        target,
+                        platform,
+                        labels,
+                        repository,
+                        reference,
+                        no_cache,
+                        progress,
+                    )
+                    .await
+            }
+            SupportedDriver::Podman(inner) => {
+                inner
+                    .build(
+                        context,
+                        file,
+                        build_args,
+                        secrets,
+                        ssh_sockets,
+                        target,
+                        platform,
+                        labels,
+                        repository,
+                        reference,
+                        no_cache,
+                        progress,
+                    )
+                    .await
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run(
+        &self,
+        repository: &str,
+        reference: &Reference,
+        name: &str,
+        mounts: Vec<Mount>,
+        entrypoint: Option<String>,
+        cmd: Option<String>,
+        args: Option<Vec<String>>,
+        env_vars: Vec<EnvVar>,
+        env_files: Vec<PathBuf>,
+        workdir: Option<PathBuf>,
+        init: Option<bool>,
+        ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        platform: Option<Platform>,
+        cap_add: Vec<String>,
+        cap_drop: Vec<String>,
+        read_only: bool,
+        privileged: bool,
+        labels: HashMap<String, String>,
+        user: Option<String>,
+        seccomp: Option<Seccomp>,
+        extra_hosts: Vec<String>,
+        networks: Vec<String>,
+        stop_signal: Option<String>,
+        stop_timeout: Option<Duration>,
+        detach: bool,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        match self {
+            SupportedDriver::DockerCliCompatible(inner) => {
+                inner
+                    .run(
+                        repository,
+                        reference,
+                        name,
+                        mounts,
+                        entrypoint,
+                        cmd,
+                        args,
+                        env_vars,
+                        env_files,
+                        workdir,
+                        init,
+                        ports,
+                        healthcheck,
+                        platform,
+                        cap_add,
+                        cap_drop,
+                        read_only,
+                        privileged,
+                        labels,
+                        user,
+                        seccomp,
+                        extra_hosts,
+                        networks,
+                        stop_signal,
+                        stop_timeout,
+                        detach,
+                        tty,
+                        stdin,
+                        stdout,
+                        stderr,
+                    )
+                    .await
+            }
+            SupportedDriver::Podman(inner) => {
+                inner
+                    .run(
+                        repository,
+                        reference,
+                        name,
+                        mounts,
+                        entrypoint,
+                        cmd,
+                        args,
+                        env_vars,
+                        env_files,
+                        workdir,
+                        init,
+                        ports,
+                        healthcheck,
+                        platform,
+                        cap_add,
+                        cap_drop,
+                        read_only,
+                        privileged,
+                        labels,
+                        user,
+                        seccomp,
+                        extra_hosts,
+                        networks,
+                        stop_signal,
+                        stop_timeout,
+                        detach,
+                        tty,
+                        stdin,
+                        stdout,
+                        stderr,
+                    )
+                    .await
+            }
+        }
+    }
 }
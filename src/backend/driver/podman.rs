@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+
+use crate::backend::driver::DockerCliCompatible;
+use crate::backend::progress::Progress;
+use crate::backend::{BuildArg, Driver, EnvVar, Mount, RunningContainer, Seccomp, Secret, Ssh};
+use crate::config::{Digest, HealthCheck, Platform, Reference, RegistrySource};
+
+const SELINUX_ENFORCE: &str = "/sys/fs/selinux/enforce";
+
+fn is_rootless() -> bool {
+    fs::metadata("/proc/self").is_ok_and(|metadata| metadata.uid() != 0)
+}
+
+fn selinux_enforcing() -> bool {
+    fs::read_to_string(SELINUX_ENFORCE).is_ok_and(|enforce| enforce.trim() == "1")
+}
+
+/// Podman accepts the Docker CLI, only running containers needs extra arguments
+pub struct PodmanDriver {
+    docker: DockerCliCompatible,
+    rootless: bool,
+    selinux: bool,
+}
+
+impl PodmanDriver {
+    pub fn new(docker: DockerCliCompatible) -> Self {
+        PodmanDriver {
+            docker,
+            rootless: is_rootless(),
+            selinux: selinux_enforcing(),
+        }
+    }
+
+    pub fn binary(&self) -> &Path {
+        self.docker.binary()
+    }
+
+    /// Rootless containers keep the user id of the host user, so files created in bind mounts are
+    /// owned by that user. Labeling is disabled for bind mounts when SELinux enforces, as the
+    /// mounted host directories would otherwise be denied.
+    fn run_arguments(&self, mounts: &[Mount]) -> Vec<String> {
+        let mut arguments = vec![];
+        if self.rootless {
+            arguments.push("--userns=keep-id".to_string());
+        }
+        if self.selinux && mounts.iter().any(|mount| mount.tmpfs.is_none()) {
+            arguments.push("--security-opt".to_string());
+            arguments.push("label=disable".to_string());
+        }
+
+        arguments
+    }
+}
+
+#[async_trait]
+impl Driver for PodmanDriver {
+    async fn kill(&self, name: &str, signal: &str) -> Result<()> {
+        self.docker.kill(name, signal).await
+    }
+
+    async fn stop(&self, name: &str, timeout: Option<Duration>) -> Result<()> {
+        self.docker.stop(name, timeout).await
+    }
+
+    async fn path(&self, repository: &str, reference: &Reference) -> Result<Option<String>> {
+        self.docker.path(repository, reference).await
+    }
+
+    async fn version(&self) -> Result<String> {
+        self.docker.version().await
+    }
+
+    async fn pull(
+        &self,
+        image: &RegistrySource,
+        platform: Option<Platform>,
+        progress: Progress,
+    ) -> Result<()> {
+        self.docker.pull(image, platform, progress).await
+    }
+
+    async fn network_exists(&self, name: &str) -> Result<bool> {
+        self.docker.network_exists(name).await
+    }
+
+    async fn create_network(
+        &self,
+        name: &str,
+        driver: &str,
+        internal: bool,
+        labels: HashMap<String, String>,
+    ) -> Result<()> {
+        self.docker
+            .create_network(name, driver, internal, labels)
+            .await
+    }
+
+    async fn networks(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        self.docker.networks(label).await
+    }
+
+    async fn remove_network(&self, name: &str) -> Result<()> {
+        self.docker.remove_network(name).await
+    }
+
+    async fn containers(&self, label: (&str, &str)) -> Result<Vec<String>> {
+        self.docker.containers(label).await
+    }
+
+    async fn remove_container(&self, id: &str) -> Result<()> {
+        self.docker.remove_container(id).await
+    }
+
+    async fn login(&self, registry: &str) -> Result<()> {
+        self.docker.login(registry).await
+    }
+
+    async fn logout(&self, registry: &str) -> Result<()> {
+        self.docker.logout(registry).await
+    }
+
+    async fn list(&self, all: bool, labels: &[(&str, &str)]) -> Result<Vec<RunningContainer>> {
+        self.docker.list(all, labels).await
+    }
+
+    async fn exists(&self, repository: &str, reference: &Reference) -> Result<bool> {
+        self.docker.exists(repository, reference).await
+    }
+
+    async fn digest(&self, repository: &str, reference: &Reference) -> Result<Option<Digest>> {
+        self.docker.digest(repository, reference).await
+    }
+
+    async fn remote_digest(&self, image: &RegistrySource) -> Result<Digest> {
+        self.docker.remote_digest(image).await
+    }
+
+    async fn exec(
+        &self,
+        id: &str,
+        workdir: Option<PathBuf>,
+        command: Vec<String>,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        self.docker
+            .exec(id, workdir, command, tty, stdin, stdout, stderr)
+            .await
+    }
+
+    async fn logs(
+        &self,
+        id: &str,
+        follow: bool,
+        tail: Option<u32>,
+        since: Option<Duration>,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        self.docker
+            .logs(id, follow, tail, since, stdout, stderr)
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn build<C, F>(
+        &self,
+        context: C,
+        file: F,
+        build_args: Vec<BuildArg>,
+        secrets: Vec<Secret>,
+        ssh_sockets: Vec<Ssh>,
+        target: Option<String>,
+        platform: Option<Platform>,
+        labels: HashMap<String, String>,
+        repository: &str,
+        reference: &Reference,
+        no_cache: bool,
+        progress: Progress,
+    ) -> Result<()>
+    where
+        C: AsRef<Path> + Send,
+        F: AsRef<Path> + Send,
+    {
+        self.docker
+            .build(
+                context,
+                file,
+                build_args,
+                secrets,
+                ssh_sockets,
+                target,
+                platform,
+                labels,
+                repository,
+                reference,
+                no_cache,
+                progress,
+            )
+            .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn run(
+        &self,
+        repository: &str,
+        reference: &Reference,
+        name: &str,
+        mounts: Vec<Mount>,
+        entrypoint: Option<String>,
+        cmd: Option<String>,
+        args: Option<Vec<String>>,
+        env_vars: Vec<EnvVar>,
+        env_files: Vec<PathBuf>,
+        workdir: Option<PathBuf>,
+        init: Option<bool>,
+        ports: HashMap<u16, u16>,
+        healthcheck: Option<HealthCheck>,
+        platform: Option<Platform>,
+        cap_add: Vec<String>,
+        cap_drop: Vec<String>,
+        read_only: bool,
+        privileged: bool,
+        labels: HashMap<String, String>,
+        user: Option<String>,
+        seccomp: Option<Seccomp>,
+        extra_hosts: Vec<String>,
+        networks: Vec<String>,
+        stop_signal: Option<String>,
+        stop_timeout: Option<Duration>,
+        detach: bool,
+        tty: bool,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus> {
+        let docker = self.docker.with_run_arguments(self.run_arguments(&mounts));
+        docker
+            .run(
+                repository,
+                reference,
+                name,
+                mounts,
+                entrypoint,
+                cmd,
+                args,
+                env_vars,
+                env_files,
+                workdir,
+                init,
+                ports,
+                healthcheck,
+                platform,
+                cap_add,
+                cap_drop,
+                read_only,
+                privileged,
+                labels,
+                user,
+                seccomp,
+                extra_hosts,
+                networks,
+                stop_signal,
+                stop_timeout,
+                detach,
+                tty,
+                stdin,
+                stdout,
+                stderr,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Tmpfs;
+
+    fn driver(rootless: bool, selinux: bool) -> PodmanDriver {
+        PodmanDriver {
+            docker: DockerCliCompatible::new("podman", PathBuf::from("podman")),
+            rootless,
+            selinux,
+        }
+    }
+
+    fn mount(tmpfs: Option<Tmpfs>) -> Mount {
+        Mount {
+            source: PathBuf::from("/src"),
+            consistency: Default::default(),
+            propagation: Default::default(),
+            non_recursive: Default::default(),
+            tmpfs,
+            target: PathBuf::from("/target"),
+            readonly: false,
+        }
+    }
+
+    #[test]
+    fn rootless_keeps_user_id() {
+        assert_eq!(
+            driver(true, false).run_arguments(&[mount(None)]),
+            vec!["--userns=keep-id"]
+        );
+        assert!(driver(false, false)
+            .run_arguments(&[mount(None)])
+            .is_empty());
+    }
+
+    #[test]
+    fn selinux_disables_labels_for_bind_mounts() {
+        assert_eq!(
+            driver(false, true).run_arguments(&[mount(None)]),
+            vec!["--security-opt", "label=disable"]
+        );
+        let tmpfs = Tmpfs {
+            size: None,
+            mode: None,
+        };
+        assert!(driver(false, true)
+            .run_arguments(&[mount(Some(tmpfs))])
+            .is_empty());
+    }
+}
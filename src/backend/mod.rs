@@ -1,21 +1,53 @@
 pub mod driver;
+pub mod progress;
 pub mod script;
 
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::future::Future;
 use std::net::TcpListener;
 use std::path::{Path, PathBuf};
-use std::process::Stdio;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
 use std::{env, fmt, fs};
 
 use anyhow::{anyhow, bail, Context, Result};
+use itertools::Itertools;
 use rand::{thread_rng, Rng};
+use serde_derive::{Deserialize, Serialize};
 
 use crate::backend::driver::Driver;
-use crate::config::{Config, ContainerConfig, HostPort, Port, Reference, Volume};
+use crate::backend::progress::Progress;
+use crate::config::{
+    BuildSource, Config, ContainerConfig, Digest, HostPort, Port, PullPolicy, Reference, Volume,
+};
 use crate::metadata::APPLICATION_NAME;
 use crate::{config, dirs};
 
+pub const INHERIT_ENVVARS_VARIABLE: &str = "TOIP_INHERIT_ENVVARS";
+/// Overrides how many random host ports are tried for a generated port
+pub const PORT_ATTEMPTS_VARIABLE: &str = "TOIP_PORT_ATTEMPTS";
+const PORT_ATTEMPTS: usize = 100;
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+const IMAGE_TAG_LENGTH: usize = 16;
+const CONFIG_HASH_LABEL: &str = "toip.config.hash";
+const CONTAINER_NAME_ANNOTATION: &str = "toip.container.name";
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+const READ_ONLY_TMPFS_PATHS: [&str; 2] = ["/tmp", "/run"];
+
+fn port_attempts() -> Result<usize> {
+    match env::var(PORT_ATTEMPTS_VARIABLE) {
+        Ok(attempts) => attempts.parse().with_context(|| {
+            format!(
+                "`{}` must be a number of attempts, got `{}`",
+                PORT_ATTEMPTS_VARIABLE, attempts
+            )
+        }),
+        Err(_) => Ok(PORT_ATTEMPTS),
+    }
+}
+
 fn container_bin_dir() -> String {
     format!("/usr/bin/{}", APPLICATION_NAME)
 }
@@ -28,7 +60,10 @@ fn container_socket() -> String {
     format!("/run/{}/sock", APPLICATION_NAME)
 }
 
-#[allow(dead_code)]
+/// Whether mounts made below a bind mount are visible on the other side of it,
+/// `shared` or `rshared` is needed to see mounts made inside another container, i.e. FUSE
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BindPropagation {
     Shared,
     Slave,
@@ -57,7 +92,8 @@ impl fmt::Display for BindPropagation {
     }
 }
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BindConsistency {
     Consistent,
     Cached,
@@ -95,11 +131,22 @@ impl BindNonRecursive {
     }
 }
 
+pub enum Seccomp {
+    Unconfined,
+    Profile(PathBuf),
+}
+
+pub struct Tmpfs {
+    size: Option<String>,
+    mode: Option<u32>,
+}
+
 pub struct Mount {
     source: PathBuf,
     consistency: BindConsistency,
     propagation: BindPropagation,
     non_recursive: BindNonRecursive,
+    tmpfs: Option<Tmpfs>,
     target: PathBuf,
     #[allow(dead_code)]
     readonly: bool,
@@ -126,6 +173,71 @@ pub struct EnvVar {
     value: String,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrepareAction {
+    Pulled,
+    Built,
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct RunningContainer {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub status: String,
+    pub running_for: String,
+    pub ports: String,
+}
+
+/// Mount target of the configuration directory when `auto_mount_cwd` is not configured
+const DEFAULT_CWD_MOUNT: &str = "/workspace";
+
+/// Searched in order when the build file is not configured
+pub const BUILD_FILE_NAMES: [&str; 4] =
+    ["Dockerfile", "Containerfile", "dockerfile", "containerfile"];
+
+/// Source of the working directory mount and the working directory within the container
+fn cwd_mount(current_dir: &Path, config_dir: &Path, target: &Path) -> (PathBuf, PathBuf) {
+    match current_dir.strip_prefix(config_dir) {
+        Ok(relative) => (config_dir.to_path_buf(), target.join(relative)),
+        // Outside of the configuration directory only the current directory is shared
+        Err(_) => (current_dir.to_path_buf(), target.to_path_buf()),
+    }
+}
+
+fn find_build_file(context: &Path) -> Result<PathBuf> {
+    let candidates: Vec<PathBuf> = BUILD_FILE_NAMES
+        .iter()
+        .map(|name| context.join(name))
+        .collect();
+
+    match candidates.iter().find(|candidate| candidate.is_file()) {
+        Some(file) => Ok(file.clone()),
+        None => bail!(
+            "could not find a build file, searched `{}`",
+            candidates
+                .iter()
+                .map(|candidate| candidate.display())
+                .join("`, `")
+        ),
+    }
+}
+
+/// The build context, relative paths are relative to the configuration directory
+fn build_context(build: &BuildSource, config_dir: &Path) -> PathBuf {
+    config_dir.join(&build.context)
+}
+
+/// The configured build file, or the first one found in the build context
+fn build_file(build: &BuildSource, config_dir: &Path) -> Result<PathBuf> {
+    match &build.file {
+        None => find_build_file(&build_context(build, config_dir)),
+        Some(file) => Ok(config_dir.join(file)),
+    }
+}
+
 pub struct Backend<D>
 where
     D: Driver,
@@ -134,6 +246,10 @@ where
     current_exe: PathBuf,
     socket: PathBuf,
     driver: D,
+    progress: Progress,
+    no_cache: bool,
+    detach: bool,
+    tty: bool,
 }
 
 pub trait Image {
@@ -154,6 +270,10 @@ where
             current_exe,
             socket: "".into(),
             driver: D::default(),
+            progress: Progress::None,
+            no_cache: false,
+            detach: false,
+            tty: false,
         }
     }
 }
@@ -174,9 +294,37 @@ where
             current_exe,
             socket: socket.into(),
             driver,
+            progress: Progress::None,
+            no_cache: false,
+            detach: false,
+            tty: false,
         }
     }
 
+    /// Report the output of pulls and builds
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress.resolve();
+        self
+    }
+
+    /// Pull and build the images again, ignoring the images and layers which are present
+    pub fn with_no_cache(mut self, no_cache: bool) -> Self {
+        self.no_cache = no_cache;
+        self
+    }
+
+    /// Start the containers in the background instead of waiting for them to exit
+    pub fn with_detach(mut self, detach: bool) -> Self {
+        self.detach = detach;
+        self
+    }
+
+    /// Allocate a terminal for the containers, only when their input is a terminal
+    pub fn with_tty(mut self, tty: bool) -> Self {
+        self.tty = tty;
+        self
+    }
+
     fn image_bin_dir<C>(&self, config_dir: C) -> Result<PathBuf>
     where
         C: AsRef<OsStr>,
@@ -196,25 +344,217 @@ where
         Ok(format!("{}-{}", digest, container_name))
     }
 
-    pub async fn prepare<P>(
+    pub fn image_name<P>(
         &self,
         container_name: &str,
         config: &ContainerConfig,
         config_dir: P,
-    ) -> anyhow::Result<()>
+    ) -> Result<(String, Reference)>
+    where
+        P: AsRef<OsStr>,
+    {
+        // Built images are tagged with the hash of their build configuration,
+        // so a changed configuration never uses a stale image
+        let reference = match &config.image {
+            None => {
+                let build_file = match &config.build {
+                    Some(build) => {
+                        let file = build_file(build, Path::new(config_dir.as_ref()))?;
+                        let contents = fs::read(&file).with_context(|| {
+                            format!("could not read build file `{}`", file.display())
+                        })?;
+                        Some(contents)
+                    }
+                    None => None,
+                };
+                let hash = config.content_hash(build_file.as_deref())?;
+                Reference::Tag(hash[..IMAGE_TAG_LENGTH].to_string())
+            }
+            Some(image) => image.reference.clone(),
+        };
+
+        let repository = match &config.image {
+            None => self.image_id(config_dir, container_name)?,
+            Some(image) => image.repository.clone(),
+        };
+
+        Ok((repository, reference))
+    }
+
+    /// Instances of the container started from the configuration directory, found by their labels
+    pub async fn running<P>(
+        &self,
+        container_name: &str,
+        config_dir: P,
+        all: bool,
+    ) -> Result<Vec<RunningContainer>>
+    where
+        P: AsRef<OsStr>,
+    {
+        let digest = config::hash(config_dir)?;
+        let labels = [
+            (CONFIG_HASH_LABEL, digest.as_str()),
+            (CONTAINER_NAME_ANNOTATION, container_name),
+        ];
+
+        self.driver
+            .list(all, &labels)
+            .await
+            .context("could not list containers")
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn exec<P>(
+        &self,
+        container_name: &str,
+        config_dir: P,
+        workdir: Option<PathBuf>,
+        command: Vec<String>,
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus>
+    where
+        P: AsRef<OsStr>,
+    {
+        let running = self.running(container_name, config_dir, false).await?;
+        let container = running
+            .first()
+            .ok_or_else(|| anyhow!("container `{}` is not running", container_name))?;
+
+        log::info!(
+            "executing `{}` in container `{}`",
+            command.join(" "),
+            container.id
+        );
+        self.driver
+            .exec(
+                &container.id,
+                workdir,
+                command,
+                self.tty,
+                stdin,
+                stdout,
+                stderr,
+            )
+            .await
+    }
+
+    /// Send the signal to the running instances of the container
+    pub async fn kill<P>(&self, container_name: &str, config_dir: P, signal: &str) -> Result<()>
+    where
+        P: AsRef<OsStr>,
+    {
+        let running = self.running(container_name, config_dir, false).await?;
+        if running.is_empty() {
+            bail!("container `{}` is not running", container_name);
+        }
+
+        for container in running {
+            log::info!("sending `{}` to container `{}`", signal, container.id);
+            self.driver
+                .kill(&container.id, signal)
+                .await
+                .with_context(|| format!("could not signal container `{}`", container.id))?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub async fn logs<P>(
+        &self,
+        container_name: &str,
+        config_dir: P,
+        follow: bool,
+        tail: Option<u32>,
+        since: Option<Duration>,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<ExitStatus>
     where
         P: AsRef<OsStr>,
     {
-        if let Some(build) = &config.build {
+        let running = self.running(container_name, config_dir, false).await?;
+        let container = running
+            .first()
+            .ok_or_else(|| anyhow!("container `{}` is not running", container_name))?;
+
+        log::info!("showing logs of container `{}`", container.id);
+        self.driver
+            .logs(&container.id, follow, tail, since, stdout, stderr)
+            .await
+    }
+
+    /// Resolve the repository and content digest of the prepared image, if it has one
+    pub async fn digest<P>(
+        &self,
+        container_name: &str,
+        config: &ContainerConfig,
+        config_dir: P,
+    ) -> Result<Option<(String, Digest)>>
+    where
+        P: AsRef<OsStr>,
+    {
+        let (repository, reference) = self.image_name(container_name, config, config_dir)?;
+        let digest = self
+            .driver
+            .digest(&repository, &reference)
+            .await
+            .with_context(|| format!("could not inspect image `{}:{}`", repository, reference))?;
+
+        Ok(digest.map(|digest| (repository, digest)))
+    }
+
+    /// Resolve the digest the registry currently serves for the configured tag
+    pub async fn remote_digest(&self, config: &ContainerConfig) -> Result<Option<Digest>> {
+        let image = match &config.image {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+        if let Reference::Digest(_) = image.reference {
+            return Ok(None);
+        }
+
+        let digest = self
+            .driver
+            .remote_digest(image)
+            .await
+            .with_context(|| format!("could not inspect remote image `{}`", image))?;
+
+        Ok(Some(digest))
+    }
+
+    pub async fn is_prepared<P>(
+        &self,
+        container_name: &str,
+        config: &ContainerConfig,
+        config_dir: P,
+    ) -> Result<bool>
+    where
+        P: AsRef<OsStr>,
+    {
+        let (repository, reference) = self.image_name(container_name, config, config_dir)?;
+        self.driver
+            .exists(&repository, &reference)
+            .await
+            .with_context(|| format!("could not inspect image `{}:{}`", repository, reference))
+    }
+
+    /// Build or pull the image of the container without creating the linked scripts
+    pub async fn pull<P>(
+        &self,
+        container_name: &str,
+        config: &ContainerConfig,
+        config_dir: P,
+    ) -> anyhow::Result<PrepareAction>
+    where
+        P: AsRef<OsStr>,
+    {
+        let action = if let Some(build) = &config.build {
             // TODO tag using image when defined
-            let file = match &build.file {
-                None => {
-                    let mut path = build.context.clone();
-                    path.push("Dockerfile");
-                    path
-                }
-                Some(file) => file.clone(),
-            };
+            let context = build_context(build, Path::new(config_dir.as_ref()));
+            let file = build_file(build, Path::new(config_dir.as_ref()))?;
 
             let build_args = build
                 .build_args
@@ -243,43 +583,80 @@ where
                 })
                 .collect();
 
-            let reference = match &config.image {
-                None => Reference::default(),
-                Some(image) => image.reference.clone(),
-            };
-
-            let repository = match &config.image {
-                None => self.image_id(&config_dir, container_name)?,
-                Some(image) => image.repository.clone(),
-            };
+            let (repository, reference) = self.image_name(container_name, config, &config_dir)?;
 
             self.driver
                 .build(
-                    &build.context,
+                    &context,
                     file,
                     build_args,
                     secrets,
                     ssh,
                     build.target.clone(),
+                    config.platform.clone(),
+                    self.create_labels(config, &config_dir)?,
                     &repository,
                     &reference,
+                    self.no_cache,
+                    self.progress,
                 )
                 .await
                 .with_context(|| {
                     format!(
                         "could not build image from build context `{}`",
-                        &build.context.display()
+                        context.display()
                     )
                 })?;
+            PrepareAction::Built
         } else if let Some(image) = &config.image {
-            self.driver
-                .pull(image)
-                .await
-                .with_context(|| format!("could not pull image `{}`", &image))?;
+            // Images referenced by digest are immutable, no need to pull them again
+            let policy = match (self.no_cache, &config.pull_policy, &image.reference) {
+                (true, _, _) => PullPolicy::Always,
+                (false, Some(policy), _) => policy.clone(),
+                (false, None, Reference::Digest(_)) => PullPolicy::IfNotPresent,
+                (false, None, Reference::Tag(_)) => PullPolicy::Always,
+            };
+            let cached = match policy {
+                PullPolicy::Always => false,
+                PullPolicy::IfNotPresent | PullPolicy::Never => self
+                    .driver
+                    .exists(&image.repository, &image.reference)
+                    .await
+                    .unwrap_or(false),
+            };
+
+            if cached {
+                PrepareAction::Skipped
+            } else if policy == PullPolicy::Never {
+                bail!(
+                    "image `{}` is not present and its pull policy is `never`",
+                    &image
+                );
+            } else {
+                self.driver
+                    .pull(image, config.platform.clone(), self.progress)
+                    .await
+                    .with_context(|| format!("could not pull image `{}`", &image))?;
+                PrepareAction::Pulled
+            }
         } else {
             bail!("missing image or build config");
         };
 
+        Ok(action)
+    }
+
+    pub async fn prepare<P>(
+        &self,
+        container_name: &str,
+        config: &ContainerConfig,
+        config_dir: P,
+    ) -> anyhow::Result<PrepareAction>
+    where
+        P: AsRef<OsStr>,
+    {
+        let action = self.pull(container_name, config, &config_dir).await?;
+
         let bin_dir = self.image_bin_dir(&config_dir)?;
 
         // TODO if image_dir exists, skip creation of scripts
@@ -302,7 +679,7 @@ where
                 .context("could not create call script")?;
         }
 
-        Ok(())
+        Ok(action)
     }
 
     fn create_mounts<P>(
@@ -320,6 +697,7 @@ where
                 consistency: Default::default(),
                 propagation: Default::default(),
                 non_recursive: Default::default(),
+                tmpfs: None,
                 target: container_bin_dir().into(),
                 readonly: true,
             },
@@ -328,18 +706,24 @@ where
                 consistency: Default::default(),
                 propagation: Default::default(),
                 non_recursive: Default::default(),
+                tmpfs: None,
                 target: container_binary().into(),
                 readonly: true,
             },
-            Mount {
+        ];
+
+        // Without a call listener, e.g. when detached, there is no socket to mount
+        if !self.socket.as_os_str().is_empty() {
+            mounts.push(Mount {
                 source: self.socket.clone(),
                 consistency: Default::default(),
                 propagation: Default::default(),
                 non_recursive: Default::default(),
+                tmpfs: None,
                 target: container_socket().into(),
                 readonly: true,
-            },
-        ];
+            });
+        }
 
         let config_dir = config_dir.into();
         for (destination, volume) in volumes {
@@ -362,6 +746,7 @@ where
                         consistency: Default::default(),
                         propagation: Default::default(),
                         non_recursive: Default::default(),
+                        tmpfs: None,
                         target: destination.clone(),
                         readonly: false,
                     });
@@ -377,9 +762,24 @@ where
                     };
                     mounts.push(Mount {
                         source,
+                        consistency: bind.consistency.clone().unwrap_or_default(),
+                        propagation: bind.propagation.clone().unwrap_or_default(),
+                        non_recursive: BindNonRecursive(bind.non_recursive.unwrap_or(false)),
+                        tmpfs: None,
+                        target: destination.clone(),
+                        readonly: false,
+                    });
+                }
+                Volume::Tmpfs(tmpfs) => {
+                    mounts.push(Mount {
+                        source: PathBuf::new(),
                         consistency: Default::default(),
                         propagation: Default::default(),
                         non_recursive: Default::default(),
+                        tmpfs: Some(Tmpfs {
+                            size: tmpfs.size,
+                            mode: tmpfs.mode,
+                        }),
                         target: destination.clone(),
                         readonly: false,
                     });
@@ -390,8 +790,267 @@ where
         Ok(mounts)
     }
 
-    fn create_env_vars(&self, path: String, config: &ContainerConfig) -> Vec<EnvVar> {
+    /// Add tmpfs mounts for the common writable paths of a read-only container
+    fn add_writable_mounts(&self, mounts: &mut Vec<Mount>) {
+        let added: Vec<&str> = READ_ONLY_TMPFS_PATHS
+            .iter()
+            .filter(|path| !mounts.iter().any(|mount| mount.target == Path::new(path)))
+            .copied()
+            .collect();
+
+        for path in &added {
+            mounts.push(Mount {
+                source: PathBuf::new(),
+                consistency: Default::default(),
+                propagation: Default::default(),
+                non_recursive: Default::default(),
+                tmpfs: Some(Tmpfs {
+                    size: None,
+                    mode: None,
+                }),
+                target: path.into(),
+                readonly: false,
+            });
+        }
+
+        if !added.is_empty() {
+            log::debug!(
+                "adding tmpfs mounts `{}` to read-only container",
+                added.join(", ")
+            );
+        }
+    }
+
+    /// Unique name for a single invocation of the container
+    fn container_id(&self, container_name: &str) -> String {
+        let sanitized: String = container_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let suffix: u32 = thread_rng().gen();
+
+        format!("{}-{}-{:08x}", APPLICATION_NAME, sanitized, suffix)
+    }
+
+    /// Stop the detached container, falls back to the running instances of the container
+    /// when it was not started detached
+    pub async fn stop(
+        &self,
+        container_name: &str,
+        config_dir: &Path,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        let path = dirs::running(config_dir, container_name)?;
+        let names = if path.exists() {
+            let name = fs::read_to_string(&path)
+                .with_context(|| format!("could not read file `{}`", path.display()))?;
+            vec![name.trim().to_string()]
+        } else {
+            log::warn!(
+                "container `{}` was not started detached, stopping its running instances",
+                container_name
+            );
+            self.running(container_name, config_dir, false)
+                .await?
+                .into_iter()
+                .map(|container| container.id)
+                .collect()
+        };
+        if names.is_empty() {
+            bail!("container `{}` is not running", container_name);
+        }
+
+        for name in names {
+            log::info!("stopping container `{}`", name);
+            self.driver
+                .stop(&name, timeout)
+                .await
+                .with_context(|| format!("could not stop container `{}`", name))?;
+        }
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("could not remove file `{}`", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Remember the name of the detached container, so it can be found again later
+    fn record_detached(&self, container_name: &str, config_dir: &Path, name: &str) -> Result<()> {
+        let path = dirs::running(config_dir, container_name)?;
+        if let Some(dir) = path.parent() {
+            dirs::create(dir)?;
+        }
+
+        fs::write(&path, name)
+            .with_context(|| format!("could not write to file `{}`", path.display()))
+    }
+
+    /// Networks are shared by the containers of a single configuration directory
+    fn network_name<P>(&self, network: &str, config_dir: P) -> Result<String>
+    where
+        P: AsRef<OsStr>,
+    {
+        let digest = config::hash(config_dir)?;
+
+        Ok(format!("{}-{}-{}", APPLICATION_NAME, &digest[..8], network))
+    }
+
+    /// Create the networks of the container which do not exist yet
+    pub async fn create_networks<P>(
+        &self,
+        config: &Config,
+        container_config: &ContainerConfig,
+        config_dir: P,
+    ) -> Result<()>
+    where
+        P: AsRef<OsStr>,
+    {
+        for network in &container_config.networks {
+            let definition = config
+                .networks
+                .get(network)
+                .ok_or_else(|| anyhow!("missing network `{}` in config", network))?;
+            let name = self.network_name(network, &config_dir)?;
+
+            if self.driver.network_exists(&name).await? {
+                continue;
+            }
+
+            log::info!("creating network `{}`", name);
+            let mut labels = HashMap::new();
+            labels.insert(CONFIG_HASH_LABEL.to_string(), config::hash(&config_dir)?);
+            self.driver
+                .create_network(&name, &definition.driver, definition.internal, labels)
+                .await
+                .with_context(|| format!("could not create network `{}`", name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove all networks created for the configuration directory
+    pub async fn remove_networks<P>(&self, config_dir: P) -> Result<Vec<String>>
+    where
+        P: AsRef<OsStr>,
+    {
+        let digest = config::hash(config_dir)?;
+        let networks = self
+            .driver
+            .networks((CONFIG_HASH_LABEL, &digest))
+            .await
+            .context("could not list networks")?;
+
+        for network in &networks {
+            self.driver
+                .remove_network(network)
+                .await
+                .with_context(|| format!("could not remove network `{}`", network))?;
+        }
+
+        Ok(networks)
+    }
+
+    /// Remove all containers, running or not, started for the configuration directory
+    pub async fn remove_containers<P>(&self, config_dir: P) -> Result<Vec<String>>
+    where
+        P: AsRef<OsStr>,
+    {
+        let digest = config::hash(config_dir)?;
+        let containers = self
+            .driver
+            .containers((CONFIG_HASH_LABEL, &digest))
+            .await
+            .context("could not list containers")?;
+
+        for container in &containers {
+            self.driver
+                .remove_container(container)
+                .await
+                .with_context(|| format!("could not remove container `{}`", container))?;
+        }
+
+        Ok(containers)
+    }
+
+    fn create_labels<P>(
+        &self,
+        config: &ContainerConfig,
+        config_dir: P,
+    ) -> Result<HashMap<String, String>>
+    where
+        P: AsRef<OsStr>,
+    {
+        let mut labels = config.labels.clone();
+        labels.insert(CONFIG_HASH_LABEL.to_string(), config::hash(config_dir)?);
+
+        Ok(labels)
+    }
+
+    /// Docker has no annotations for containers, they are added as labels instead
+    fn create_annotations(
+        &self,
+        container_name: &str,
+        config: &ContainerConfig,
+        reference: &Reference,
+    ) -> HashMap<String, String> {
+        let mut annotations = config.annotations.clone();
+        annotations
+            .entry(TITLE_ANNOTATION.to_string())
+            .or_insert_with(|| container_name.to_string());
+        annotations
+            .entry(VERSION_ANNOTATION.to_string())
+            .or_insert_with(|| reference.to_string());
+        annotations.insert(
+            CONTAINER_NAME_ANNOTATION.to_string(),
+            container_name.to_string(),
+        );
+
+        annotations
+    }
+
+    /// The variables sent along with the call take precedence over the variables of toip itself
+    fn create_env_vars(
+        &self,
+        path: String,
+        config: &ContainerConfig,
+        envargs: &HashMap<String, String>,
+    ) -> Vec<EnvVar> {
         let mut envs = vec![];
+        if config.inherit_all_envvars {
+            let mut inherited: HashMap<String, String> = env::vars().collect();
+            inherited.extend(envargs.clone());
+            for (name, value) in inherited {
+                envs.push(EnvVar { name, value });
+            }
+        } else {
+            for name in &config.inherit_envvars {
+                match envargs
+                    .get(name)
+                    .map_or_else(|| env::var(name), |value| Ok(value.clone()))
+                {
+                    Ok(value) => envs.push(EnvVar {
+                        name: name.clone(),
+                        value,
+                    }),
+                    Err(_) => {
+                        if !config.env.contains_key(name) {
+                            log::warn!(
+                                "could not inherit environment variable `{}` as it is not set",
+                                name
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
         for (name, value) in &config.env {
             envs.push(EnvVar {
                 name: name.clone(),
@@ -416,27 +1075,121 @@ where
         TcpListener::bind(("127.0.0.1", port)).is_ok()
     }
 
-    fn create_ports(&self, ports: &[Port]) -> HashMap<u16, u16> {
+    fn check_ports(&self, container_name: &str, ports: &[Port]) -> Result<()> {
+        let conflicts: Vec<u16> = ports
+            .iter()
+            .filter_map(|port| match port.host {
+                HostPort::Specified(host) if !self.is_available(host) => Some(host),
+                _ => None,
+            })
+            .collect();
+
+        if !conflicts.is_empty() {
+            bail!(
+                "container `{}` could not bind host port(s) `{}` as they are already in use",
+                container_name,
+                conflicts.iter().join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    fn create_ports(&self, ports: &[Port], max_attempts: usize) -> Result<HashMap<u16, u16>> {
         let mut generated_ports = vec![];
         let mut random = thread_rng();
-        let hashmap = ports
-            .iter()
-            .map(|port| match port.host {
-                HostPort::Specified(host) => (host, port.container),
+        let mut hashmap = HashMap::new();
+        for port in ports {
+            match port.host {
+                HostPort::Specified(host) => {
+                    hashmap.insert(host, port.container);
+                }
                 HostPort::Generated => {
-                    let mut generated = random.gen_range(1024..u16::MAX);
-                    while generated_ports.contains(&generated) && !self.is_available(generated) {
-                        generated = random.gen_range(1024..u16::MAX);
-                    }
+                    let generated = (0..max_attempts)
+                        .map(|_| random.gen_range(1024..u16::MAX))
+                        .find(|generated| {
+                            !generated_ports.contains(generated)
+                                && !hashmap.contains_key(generated)
+                                && self.is_available(*generated)
+                        })
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "could not find an available host port for container port `{}` after {} attempts",
+                                port.container,
+                                max_attempts
+                            )
+                        })?;
                     generated_ports.push(generated);
-                    (generated, port.container)
+                    hashmap.insert(generated, port.container);
                 }
-            })
-            .collect();
+            }
+        }
+
+        Ok(hashmap)
+    }
+
+    /// Mount the configuration directory and start in the equivalent of the current directory
+    fn inherit_workdir(
+        &self,
+        container_config: &ContainerConfig,
+        config_dir: &Path,
+        mounts: &mut Vec<Mount>,
+    ) -> Result<Option<PathBuf>> {
+        if container_config.inherit_workdir != Some(true) || container_config.workdir.is_some() {
+            return Ok(container_config.workdir.clone());
+        }
+
+        let target = container_config
+            .auto_mount_cwd
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CWD_MOUNT));
+        let current_dir = env::current_dir()?;
+        let (source, workdir) = cwd_mount(&current_dir, config_dir, &target);
+
+        mounts.push(Mount {
+            source,
+            consistency: Default::default(),
+            propagation: Default::default(),
+            non_recursive: Default::default(),
+            tmpfs: None,
+            target,
+            readonly: false,
+        });
+
+        Ok(Some(workdir))
+    }
+
+    /// The runtime applies its default profile when none is configured
+    fn create_seccomp(
+        &self,
+        container_config: &ContainerConfig,
+        config_dir: &Path,
+    ) -> Result<Option<Seccomp>> {
+        let profile = match &container_config.seccomp_profile {
+            Some(profile) => profile.as_ref(),
+            None => return Ok(None),
+        };
+
+        if profile == Path::new("default") {
+            return Ok(None);
+        }
+        if profile == Path::new("unconfined") {
+            return Ok(Some(Seccomp::Unconfined));
+        }
 
-        hashmap
+        let path = if profile.is_absolute() {
+            profile.to_path_buf()
+        } else {
+            config_dir.join(profile)
+        };
+        if !path.is_file() {
+            bail!("seccomp profile `{}` does not exist", path.display());
+        }
+
+        Ok(Some(Seccomp::Profile(path)))
     }
 
+    /// Run the container, returns its exit status and the name it was started with
     #[allow(clippy::too_many_arguments)]
     pub async fn spawn(
         &self,
@@ -445,10 +1198,13 @@ where
         container_config: &ContainerConfig,
         config_dir: &Path,
         args: Vec<String>,
+        envargs: HashMap<String, String>,
         stdin: Stdio,
         stdout: Stdio,
         stderr: Stdio,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<(ExitStatus, String)> {
+        self.check_ports(container_name, &container_config.ports)?;
+
         let image_bin_dir = self.image_bin_dir(&config_dir)?;
 
         let mut volumes = HashMap::new();
@@ -460,19 +1216,27 @@ where
             volumes.insert(destination.clone(), volume.clone());
         }
 
-        let mounts = self
+        let mut mounts = self
             .create_mounts(image_bin_dir, volumes, config_dir)
             .context("could not configure mounts")?;
 
-        let reference = match &container_config.image {
-            None => Reference::default(),
-            Some(image) => image.reference.clone(),
-        };
+        let read_only = container_config.read_only == Some(true);
+        if read_only {
+            self.add_writable_mounts(&mut mounts);
+        }
 
-        let repository = match &container_config.image {
-            None => self.image_id(config_dir, container_name)?,
-            Some(image) => image.repository.clone(),
-        };
+        let seccomp = self.create_seccomp(container_config, config_dir)?;
+
+        let networks = container_config
+            .networks
+            .iter()
+            .map(|network| self.network_name(network, config_dir))
+            .collect::<Result<Vec<_>>>()?;
+
+        let (repository, reference) =
+            self.image_name(container_name, container_config, config_dir)?;
+        let mut labels = self.create_labels(container_config, config_dir)?;
+        labels.extend(self.create_annotations(container_name, container_config, &reference));
 
         let path = self
             .driver
@@ -483,40 +1247,397 @@ where
                 format!("{}:{}", container_bin_dir(), &some)
             });
 
-        let env_vars = self.create_env_vars(path, container_config);
+        let mut env_vars = self.create_env_vars(path, container_config, &envargs);
+        // Tell the container which variables its links inherit, so a call sends only those
+        let inherited: Vec<&str> = container_config
+            .links
+            .values()
+            .filter_map(|target| config.containers.get(target))
+            .flat_map(|link| link.inherit_envvars.iter().map(String::as_str))
+            .sorted()
+            .dedup()
+            .collect();
+        if !inherited.is_empty() {
+            env_vars.push(EnvVar {
+                name: INHERIT_ENVVARS_VARIABLE.to_string(),
+                value: inherited.join(","),
+            });
+        }
+        // The runtime reads the env files on the host, so they do not need to be mounted
+        let env_files = container_config
+            .env_files
+            .iter()
+            .map(|env_file| {
+                let env_file = env_file.as_ref();
+                if env_file.is_absolute() {
+                    env_file.to_path_buf()
+                } else {
+                    config_dir.join(env_file)
+                }
+            })
+            .collect();
 
         let cmd = container_config.cmd.clone();
         let mut all_args = container_config.args.clone();
         all_args.extend(args);
         let entrypoint = container_config.entrypoint.clone();
-        let workdir = container_config.workdir.clone();
+        let workdir = self.inherit_workdir(container_config, config_dir, &mut mounts)?;
 
-        let ports = self.create_ports(&container_config.ports);
+        let ports = self
+            .create_ports(&container_config.ports, port_attempts()?)
+            .context("could not configure ports")?;
 
         log::info!(
             "Running container from image `{}/{}`",
             repository,
             reference
         );
-        self.driver
-            .run(
-                &repository,
-                &reference,
-                mounts,
-                entrypoint,
-                cmd,
-                Some(all_args),
-                env_vars,
-                vec![],
-                workdir,
-                None,
-                ports,
-                stdin,
-                stdout,
-                stderr,
+        let name = self.container_id(container_name);
+        let run = self.driver.run(
+            &repository,
+            &reference,
+            &name,
+            mounts,
+            entrypoint,
+            cmd,
+            Some(all_args),
+            env_vars,
+            env_files,
+            workdir,
+            container_config.init,
+            ports,
+            container_config.healthcheck.clone(),
+            container_config.platform.clone(),
+            container_config.cap_add.clone(),
+            container_config.cap_drop.clone(),
+            read_only,
+            container_config.privileged == Some(true),
+            labels,
+            container_config.user.clone(),
+            seccomp,
+            container_config.extra_hosts.clone(),
+            networks,
+            container_config.stop_signal.clone(),
+            container_config.stop_grace_period,
+            self.detach,
+            self.tty,
+            stdin,
+            stdout,
+            stderr,
+        );
+
+        if self.detach {
+            let status = run.await?;
+            if status.success() {
+                self.record_detached(container_name, config_dir, &name)?;
+            }
+            return Ok((status, name));
+        }
+
+        let timeout = match container_config.timeout {
+            None => return Ok((run.await?, name)),
+            Some(timeout) => timeout,
+        };
+
+        let stop_signal = container_config.stop_signal.as_deref().unwrap_or("SIGTERM");
+        let grace_period = container_config
+            .stop_grace_period
+            .unwrap_or(STOP_GRACE_PERIOD);
+        let status = self
+            .run_with_timeout(
+                run,
+                container_name,
+                &name,
+                timeout,
+                stop_signal,
+                grace_period,
             )
             .await?;
 
-        Ok(())
+        Ok((status, name))
+    }
+
+    /// Stop the container when it does not finish in time, it is killed when it ignores the
+    /// stop signal for the grace period
+    async fn run_with_timeout<F>(
+        &self,
+        run: F,
+        container_name: &str,
+        name: &str,
+        timeout: Duration,
+        stop_signal: &str,
+        grace_period: Duration,
+    ) -> Result<ExitStatus>
+    where
+        F: Future<Output = Result<ExitStatus>>,
+    {
+        tokio::pin!(run);
+        if let Ok(status) = tokio::time::timeout(timeout, &mut run).await {
+            return status;
+        }
+
+        log::warn!(
+            "container `{}` did not finish within {:?}, stopping it",
+            container_name,
+            timeout
+        );
+        self.driver
+            .kill(name, stop_signal)
+            .await
+            .with_context(|| format!("could not terminate container `{}`", name))?;
+
+        if tokio::time::timeout(grace_period, &mut run).await.is_err() {
+            log::warn!(
+                "container `{}` did not stop within {:?}, killing it",
+                container_name,
+                grace_period
+            );
+            if let Err(error) = self.driver.kill(name, "SIGKILL").await {
+                log::debug!("container `{}` already stopped: {:#}", name, error);
+            }
+        }
+
+        bail!(
+            "container `{}` timed out after {:?}",
+            container_name,
+            timeout
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future;
+    use std::os::unix::process::ExitStatusExt;
+    use std::time::Instant;
+
+    use super::*;
+    use crate::backend::driver::mock::MockDriver;
+
+    #[test]
+    fn cwd_mount_within_config_dir() {
+        let (source, workdir) = cwd_mount(
+            Path::new("/home/user/project/src/bin"),
+            Path::new("/home/user/project"),
+            Path::new(DEFAULT_CWD_MOUNT),
+        );
+        assert_eq!(source, Path::new("/home/user/project"));
+        assert_eq!(workdir, Path::new("/workspace/src/bin"));
+    }
+
+    #[test]
+    fn cwd_mount_outside_config_dir() {
+        let (source, workdir) = cwd_mount(
+            Path::new("/tmp/other"),
+            Path::new("/home/user/project"),
+            Path::new("/code"),
+        );
+        assert_eq!(source, Path::new("/tmp/other"));
+        assert_eq!(workdir, Path::new("/code"));
+    }
+
+    #[test]
+    fn built_image_tag_reads_build_file_from_config_dir() {
+        let config_dir = env::temp_dir().join(format!("toip-build-{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let config = ContainerConfig {
+            build: Some(BuildSource {
+                context: PathBuf::from("."),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let missing = backend.image_name("app", &config, &config_dir);
+
+        fs::write(config_dir.join("Dockerfile"), "FROM alpine:3.14").unwrap();
+        let (_, first) = backend.image_name("app", &config, &config_dir).unwrap();
+        fs::write(config_dir.join("Dockerfile"), "FROM alpine:3.15").unwrap();
+        let (_, second) = backend.image_name("app", &config, &config_dir).unwrap();
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert!(missing.is_err());
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn timed_out_container_is_killed_when_it_ignores_the_stop_signal() {
+        let driver = MockDriver::default();
+        let killed = driver.killed.clone();
+        let backend = Backend::new("docker", "", driver);
+
+        let result = backend
+            .run_with_timeout(
+                future::pending(),
+                "app",
+                "toip-app",
+                Duration::from_millis(10),
+                "SIGTERM",
+                Duration::from_millis(10),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            *killed.lock().unwrap(),
+            vec![
+                ("toip-app".to_string(), "SIGTERM".to_string()),
+                ("toip-app".to_string(), "SIGKILL".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn timed_out_container_is_not_killed_when_it_stops() {
+        let driver = MockDriver::default();
+        let killed = driver.killed.clone();
+        let stopped = driver.stopped.clone();
+        let backend = Backend::new("docker", "", driver);
+        let run = async move {
+            stopped.notified().await;
+            Ok(ExitStatus::from_raw(143 << 8))
+        };
+
+        let started = Instant::now();
+        let result = backend
+            .run_with_timeout(
+                run,
+                "app",
+                "toip-app",
+                Duration::from_millis(10),
+                "SIGINT",
+                Duration::from_secs(60),
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(60));
+        assert_eq!(
+            *killed.lock().unwrap(),
+            vec![("toip-app".to_string(), "SIGINT".to_string())]
+        );
+    }
+
+    /// Value of the variable in the environment of the container, the last one wins
+    fn env_value<'a>(envs: &'a [EnvVar], name: &str) -> Option<&'a str> {
+        envs.iter()
+            .rev()
+            .find(|env| env.name == name)
+            .map(|env| env.value.as_str())
+    }
+
+    #[test]
+    fn inherited_env_vars_are_overridden_by_configuration() {
+        env::set_var("TOIP_TEST_INHERITED", "host");
+        env::set_var("TOIP_TEST_CONFIGURED", "host");
+        env::set_var("TOIP_TEST_NOT_LISTED", "host");
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let config = ContainerConfig {
+            inherit_envvars: vec![
+                "TOIP_TEST_INHERITED".into(),
+                "TOIP_TEST_CONFIGURED".into(),
+                "TOIP_TEST_MISSING".into(),
+            ],
+            env: vec![("TOIP_TEST_CONFIGURED".into(), "config".to_string().into())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        let envs = backend.create_env_vars("/bin".into(), &config, &HashMap::new());
+
+        assert_eq!(env_value(&envs, "TOIP_TEST_INHERITED"), Some("host"));
+        assert_eq!(env_value(&envs, "TOIP_TEST_CONFIGURED"), Some("config"));
+        assert_eq!(env_value(&envs, "TOIP_TEST_MISSING"), None);
+        assert_eq!(env_value(&envs, "TOIP_TEST_NOT_LISTED"), None);
+    }
+
+    #[test]
+    fn env_vars_of_the_call_take_precedence_over_the_host() {
+        env::set_var("TOIP_TEST_CALLED", "host");
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let envargs: HashMap<String, String> =
+            vec![("TOIP_TEST_CALLED".to_string(), "call".to_string())]
+                .into_iter()
+                .collect();
+
+        let listed = ContainerConfig {
+            inherit_envvars: vec!["TOIP_TEST_CALLED".into()],
+            ..Default::default()
+        };
+        let all = ContainerConfig {
+            inherit_all_envvars: true,
+            ..Default::default()
+        };
+
+        for config in [listed, all] {
+            let envs = backend.create_env_vars("/bin".into(), &config, &envargs);
+            assert_eq!(env_value(&envs, "TOIP_TEST_CALLED"), Some("call"));
+        }
+    }
+
+    fn port(host: HostPort, container: u16) -> Port {
+        Port { host, container }
+    }
+
+    #[test]
+    fn occupied_ports_are_reported() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let occupied = listener.local_addr().unwrap().port();
+        let backend = Backend::new("docker", "", MockDriver::default());
+
+        let error = backend
+            .check_ports(
+                "web",
+                &[
+                    port(HostPort::Specified(occupied), 80),
+                    port(HostPort::Generated, 443),
+                ],
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "container `web` could not bind host port(s) `{}` as they are already in use",
+                occupied
+            )
+        );
+        drop(listener);
+        assert!(backend
+            .check_ports("web", &[port(HostPort::Specified(occupied), 80)])
+            .is_ok());
+    }
+
+    #[test]
+    fn generated_ports_are_distinct() {
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let ports = backend
+            .create_ports(
+                &[
+                    port(HostPort::Specified(8080), 80),
+                    port(HostPort::Generated, 443),
+                    port(HostPort::Generated, 8443),
+                ],
+                PORT_ATTEMPTS,
+            )
+            .unwrap();
+
+        assert_eq!(ports.len(), 3);
+        assert_eq!(ports[&8080], 80);
+        let mut containers: Vec<u16> = ports.values().copied().collect();
+        containers.sort_unstable();
+        assert_eq!(containers, vec![80, 443, 8443]);
+    }
+
+    #[test]
+    fn generating_ports_gives_up() {
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let error = backend
+            .create_ports(&[port(HostPort::Generated, 443)], 0)
+            .unwrap_err();
+
+        assert!(error.to_string().contains("after 0 attempts"), "{}", error);
     }
 }
@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::process::ExitStatus;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+const LAYER_PATTERN: &str = "^(?P<layer>[0-9a-f]{12}): (?P<status>.+)$";
+
+/// How the output of pulls and builds is reported
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// Progress bar on a terminal, plain log lines otherwise
+    Auto,
+    Plain,
+    Bar,
+    None,
+}
+
+impl FromStr for Progress {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Progress::Auto),
+            "plain" => Ok(Progress::Plain),
+            "bar" => Ok(Progress::Bar),
+            "none" => Ok(Progress::None),
+            _ => Err(anyhow!(
+                "unsupported progress `{}`, expected `auto`, `plain`, `bar` or `none`",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Progress::Auto => write!(f, "auto"),
+            Progress::Plain => write!(f, "plain"),
+            Progress::Bar => write!(f, "bar"),
+            Progress::None => write!(f, "none"),
+        }
+    }
+}
+
+impl Progress {
+    pub fn resolve(self) -> Progress {
+        match self {
+            Progress::Auto if io::stderr().is_terminal() => Progress::Bar,
+            Progress::Auto => Progress::Plain,
+            progress => progress,
+        }
+    }
+}
+
+struct Reporter {
+    bar: Option<ProgressBar>,
+    pattern: Regex,
+    layers: HashSet<String>,
+    completed: HashSet<String>,
+}
+
+impl Reporter {
+    fn new(progress: Progress) -> Self {
+        let bar = match progress {
+            Progress::Bar => {
+                let bar = ProgressBar::new(0);
+                bar.set_style(
+                    ProgressStyle::default_bar()
+                        .template("{spinner} [{bar:30}] {pos}/{len} layers {wide_msg}")
+                        .unwrap(),
+                );
+                Some(bar)
+            }
+            _ => None,
+        };
+
+        Reporter {
+            bar,
+            pattern: Regex::new(LAYER_PATTERN).unwrap(),
+            layers: HashSet::new(),
+            completed: HashSet::new(),
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let bar = match &self.bar {
+            Some(bar) => bar,
+            None => {
+                log::info!("{}", line);
+                return;
+            }
+        };
+
+        if let Some(captures) = self.pattern.captures(line) {
+            let layer = captures.name("layer").unwrap().as_str().to_string();
+            let status = captures.name("status").unwrap().as_str();
+            self.layers.insert(layer.clone());
+            if status == "Pull complete" || status == "Already exists" {
+                self.completed.insert(layer);
+            }
+            bar.set_length(self.layers.len() as u64);
+            bar.set_position(self.completed.len() as u64);
+        }
+        bar.set_message(line.to_string());
+        bar.tick();
+    }
+
+    fn finish(self) {
+        if let Some(bar) = self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+/// Run the command while reporting its output, returns the status and the collected stderr
+pub async fn report(command: &mut Command, progress: Progress) -> Result<(ExitStatus, String)> {
+    if progress == Progress::None {
+        let output = command.output().await?;
+        return Ok((
+            output.status,
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ));
+    }
+
+    command.stdout(std::process::Stdio::piped());
+    command.stderr(std::process::Stdio::piped());
+    let mut child = command.spawn()?;
+    let mut stdout = BufReader::new(child.stdout.take().context("missing stdout")?).lines();
+    let mut stderr = BufReader::new(child.stderr.take().context("missing stderr")?).lines();
+
+    let mut reporter = Reporter::new(progress);
+    let mut errors = String::new();
+    let mut stdout_open = true;
+    let mut stderr_open = true;
+    while stdout_open || stderr_open {
+        tokio::select! {
+            line = stdout.next_line(), if stdout_open => match line? {
+                Some(line) => reporter.line(&line),
+                None => stdout_open = false,
+            },
+            line = stderr.next_line(), if stderr_open => match line? {
+                Some(line) => {
+                    reporter.line(&line);
+                    errors.push_str(&line);
+                    errors.push('\n');
+                }
+                None => stderr_open = false,
+            },
+        }
+    }
+    reporter.finish();
+
+    Ok((child.wait().await?, errors))
+}
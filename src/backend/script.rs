@@ -2,46 +2,75 @@ use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
 use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
 
-fn create<D, B>(destination: D, binary: B, command: &str, argument: &str) -> Result<()>
-where
-    D: AsRef<Path>,
-    B: AsRef<Path>,
-{
-    let binary = binary.as_ref();
-    let destination = destination.as_ref();
-    let script = format!("#!{} {}\n{}\n", binary.display(), command, argument);
-
-    let mut file = File::create(&destination)
-        .with_context(|| format!("could not create file `{}`", destination.display()))?;
+fn write_executable(path: &Path, script: &str) -> Result<()> {
+    let mut file = File::create(path)
+        .with_context(|| format!("could not create file `{}`", path.display()))?;
 
     file.write_all(script.as_bytes())
-        .with_context(|| format!("could not write to file `{}`", destination.display()))?;
-
-    let mut permissions = fs::metadata(&destination)
-        .with_context(|| {
-            format!(
-                "could not read metadata for file `{}`",
-                destination.display()
-            )
-        })?
+        .with_context(|| format!("could not write to file `{}`", path.display()))?;
+    file.sync_all()
+        .with_context(|| format!("could not flush file `{}`", path.display()))?;
+
+    let mut permissions = fs::metadata(path)
+        .with_context(|| format!("could not read metadata for file `{}`", path.display()))?
         .permissions();
     permissions.set_mode(0o744);
 
-    fs::set_permissions(&destination, permissions).with_context(|| {
+    fs::set_permissions(path, permissions).with_context(|| {
         format!(
             "could not apply permissions `{}` to file `{}`",
             744,
-            destination.display()
+            path.display()
         )
     })?;
 
     Ok(())
 }
 
+/// Move the file into place, rename is atomic but only within the same filesystem
+fn move_file(source: &Path, destination: &Path) -> Result<()> {
+    if fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(source, destination).with_context(|| {
+        format!(
+            "could not copy file `{}` to `{}`",
+            source.display(),
+            destination.display()
+        )
+    })?;
+    fs::remove_file(source).with_context(|| format!("could not remove file `{}`", source.display()))
+}
+
+fn create<D, B>(destination: D, binary: B, command: &str, argument: &str) -> Result<()>
+where
+    D: AsRef<Path>,
+    B: AsRef<Path>,
+{
+    let binary = binary.as_ref();
+    let destination = destination.as_ref();
+    let script = format!("#!{} {}\n{}\n", binary.display(), command, argument);
+
+    // Write the script next to the destination first, so an interrupted write
+    // never leaves a partial executable behind
+    let mut temporary = destination.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let result =
+        write_executable(&temporary, &script).and_then(|_| move_file(&temporary, destination));
+    if result.is_err() && temporary.exists() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    result
+}
+
 pub fn create_call<D, B>(destination: D, binary: B, target: &str) -> Result<()>
 where
     D: AsRef<Path>,
@@ -1,8 +1,32 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{ArgEnum, Args, Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 
+use crate::backend::driver::DRIVERS;
+use crate::backend::progress::Progress;
+use crate::command::{parse_key_value, parse_link};
+use crate::config::{parse_duration, parse_signal, Platform};
+
+/// Scripts run through their shebang as `toip run <script> <args>`, the arguments of the script
+/// are separated with `--` so they reach the container as is
+pub fn script_arguments<I>(arguments: I) -> Vec<OsString>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut arguments: Vec<OsString> = arguments.into_iter().collect();
+    let is_script = matches!(arguments.get(1), Some(command) if command == "run" || command == "call")
+        && matches!(arguments.get(2), Some(script) if Path::new(script).is_file())
+        && !matches!(arguments.get(3), Some(separator) if separator == "--");
+    if is_script {
+        arguments.insert(3, "--".into());
+    }
+
+    arguments
+}
+
 #[derive(Parser, Debug)]
 #[clap(version, author, about)]
 #[clap(propagate_version = true)]
@@ -10,16 +34,35 @@ pub struct Cli {
     #[clap(flatten)]
     pub verbose: Verbosity,
 
+    /// Configuration profile to apply, defaults to the `TOIP_PROFILE` environment variable
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+
+    /// Configuration file or directory to use instead of searching the current directory and its parents
+    #[clap(long, global = true, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Container runtime to use: `docker`, `podman`, `nerdctl`, `lima` or `colima`, defaults to the first one installed
+    #[clap(long, global = true, possible_values = DRIVERS)]
+    pub driver: Option<String>,
+
+    /// Socket to listen on when running and to connect to when calling,
+    /// defaults to `$TOIP_SOCK_DIR/socket` or the runtime directory
+    #[clap(long, global = true, parse(from_os_str))]
+    pub socket_path: Option<PathBuf>,
+
+    /// Also write the log, as JSON lines, to this file
+    #[clap(long, global = true, parse(from_os_str))]
+    pub log_file: Option<PathBuf>,
+
+    /// Do not merge the global configuration file into the project configuration
+    #[clap(long, global = true)]
+    pub no_global_config: bool,
+
     #[clap(subcommand)]
     pub command: Command,
 }
 
-#[derive(Debug, PartialEq, Subcommand)]
-pub enum Arguments {
-    #[clap(external_subcommand)]
-    Arguments(Vec<String>),
-}
-
 #[derive(Debug, PartialEq, Subcommand)]
 #[clap(infer_subcommands = true)]
 pub enum Command {
@@ -28,6 +71,99 @@ pub enum Command {
         /// Ignore missing configuration file
         #[clap(short, long)]
         ignore_missing: bool,
+
+        /// Only log what would be installed
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Create a configuration file in the current directory
+    Init {
+        /// Overwrite an existing configuration file
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Archive the configuration so it can be imported elsewhere
+    Export {
+        /// Archive to create, defaults to `toip-export.tar.gz`
+        #[clap(parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Create a configuration file from a docker compose file or an exported archive
+    Import {
+        /// Compose file or archive to import
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+
+        /// Overwrite an existing configuration file
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Add a container to the configuration file
+    Add {
+        /// Name of the container
+        name: String,
+
+        /// Image of the container, i.e. `alpine:3.15`
+        #[clap(
+            long,
+            conflicts_with = "build-context",
+            required_unless_present = "build-context"
+        )]
+        image: Option<String>,
+
+        /// Build the image of the container from this context
+        #[clap(long, parse(from_os_str))]
+        build_context: Option<PathBuf>,
+
+        /// Link to another container, i.e. `--link db:postgres`
+        #[clap(long = "link", parse(try_from_str = parse_link))]
+        links: Vec<(String, String)>,
+
+        /// Overwrite an existing container
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Create a configuration file from a template in a template registry
+    Template {
+        /// Name of the template
+        name: String,
+
+        /// Configuration file to create, defaults to `toip.yaml`
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Host of the template registry, defaults to `$TOIP_TEMPLATE_REGISTRY`
+        #[clap(long)]
+        registry: Option<String>,
+
+        /// Value of a template variable, i.e. `--set VERSION=1.2`
+        #[clap(long = "set", parse(try_from_str = parse_key_value))]
+        values: Vec<(String, String)>,
+
+        /// Environment variable which may fill the template variable of the same name
+        #[clap(long = "env")]
+        inherit: Vec<String>,
+
+        /// Overwrite an existing configuration file
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Backup and restore the named volumes
+    Volume {
+        #[clap(subcommand)]
+        command: VolumeCommand,
+    },
+
+    /// Generate files to run containers with other tools
+    Generate {
+        #[clap(subcommand)]
+        target: GenerateTarget,
     },
 
     /// Add the current configured container into the shell
@@ -37,7 +173,38 @@ pub enum Command {
         shell: Shell,
     },
 
-    Debug {},
+    /// Print the bash completion script
+    ///
+    /// Add the following to ~/.bashrc, or use `toip inject bash`
+    ///
+    ///    source <(toip completions)
+    Completions {
+        /// Print the names of the configured containers and links instead
+        #[clap(long)]
+        dynamic: bool,
+    },
+
+    /// Print the resolved configuration of a container as JSON
+    Inspect {
+        /// Print the JSON on a single line
+        #[clap(long)]
+        raw: bool,
+
+        /// Only print this field, as JSON pointer, i.e. `/image` or `/healthcheck/test/0`
+        #[clap(long)]
+        field: Option<String>,
+
+        /// Container name
+        container: String,
+    },
+
+    /// Print the resolved configuration, paths and validation problems
+    Debug {
+        /// Output format
+        #[clap(long, arg_enum, default_value = "json")]
+        format: DebugFormat,
+    },
+
     /// Build and or pull containers
     Prepare {
         /// Container name
@@ -47,16 +214,100 @@ pub enum Command {
         /// Ignore missing configuration file
         #[clap(short, long)]
         ignore_missing: bool,
+
+        /// Platform to build or pull the images for, i.e. `linux/arm64`
+        #[clap(long)]
+        platform: Option<Platform>,
+
+        /// Allow containers which are configured to run in privileged mode
+        #[clap(long)]
+        allow_privileged: bool,
+
+        /// Print a machine readable summary as JSON
+        #[clap(long)]
+        json: bool,
+
+        /// Only log what would be built or pulled
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Report pulls and builds: `auto`, `plain`, `bar` or `none`
+        #[clap(long, default_value = "auto")]
+        progress: Progress,
+
+        /// Pull and build the images again, ignoring the images and layers which are present
+        #[clap(long)]
+        no_cache: bool,
+    },
+
+    /// Build and or pull containers without installing the linked scripts
+    Pull {
+        /// Container name
+        #[clap(short, long)]
+        container: Option<String>,
+
+        /// Ignore missing configuration file
+        #[clap(short, long)]
+        ignore_missing: bool,
+
+        /// Platform to build or pull the images for, i.e. `linux/arm64`
+        #[clap(long)]
+        platform: Option<Platform>,
+    },
+
+    /// Pin the image digests of the containers in the lock file
+    Lock {
+        /// Resolve the digests of all containers again
+        #[clap(short, long)]
+        update: bool,
+    },
+
+    /// Store the credentials of a registry with the container runtime
+    Login {
+        /// Registry host, i.e. `ghcr.io`
+        registry: String,
+    },
+
+    /// Remove the stored credentials of a registry
+    Logout {
+        /// Registry host, i.e. `ghcr.io`
+        registry: String,
+    },
+
+    /// Check the registry for newer images of the locked containers
+    Update {
+        /// Container name
+        #[clap(short, long)]
+        container: Option<String>,
+
+        /// Pull the newer images and update the lock file
+        #[clap(long)]
+        apply: bool,
     },
 
     /// Run a container
     Run {
+        /// Platform to run the containers on, i.e. `linux/arm64`
+        #[clap(long)]
+        platform: Option<Platform>,
+
+        /// Allow containers which are configured to run in privileged mode
+        #[clap(long)]
+        allow_privileged: bool,
+
+        /// Run the container again when the configuration or its sources change
+        #[clap(long)]
+        watch: bool,
+
+        /// Start the container in the background and print its name
+        #[clap(short, long, conflicts_with = "watch")]
+        detach: bool,
         /// Configuration script
         #[clap(parse(from_os_str))]
         script: PathBuf,
-        /// Argument to call the container with
-        #[clap(subcommand)]
-        args: Option<Arguments>,
+        /// Arguments to call the container with, everything after `--` is passed as is
+        #[clap(last = true)]
+        args: Vec<String>,
     },
 
     /// Run a linked container from another container
@@ -64,16 +315,181 @@ pub enum Command {
         /// Configuration script
         #[clap(parse(from_os_str))]
         script: PathBuf,
-        /// Argument to call the container with
-        #[clap(subcommand)]
-        args: Option<Arguments>,
+        /// Arguments to call the container with, everything after `--` is passed as is
+        #[clap(last = true)]
+        args: Vec<String>,
     },
 
+    /// Validate the configuration file
+    Validate {
+        /// Warn about containers without an image or build configuration
+        #[clap(short, long)]
+        strict: bool,
+    },
+
+    /// Show running containers of the current configuration
+    Status {
+        /// Refresh the status every 2 seconds
+        #[clap(short, long)]
+        watch: bool,
+    },
+
+    /// List the containers of the current configuration
+    Ps {
+        /// Include stopped containers
+        #[clap(short, long)]
+        all: bool,
+    },
+
+    /// Execute a command in a running container
+    #[clap(trailing_var_arg = true)]
+    Exec {
+        /// Working directory inside the container
+        #[clap(short, long, parse(from_os_str))]
+        workdir: Option<PathBuf>,
+        /// Container name
+        container: String,
+        /// Command to execute
+        #[clap(required = true)]
+        command: Vec<String>,
+    },
+
+    /// Stop a container which was started detached
+    Stop {
+        /// Seconds to wait for the container to stop before it is killed
+        #[clap(short, long)]
+        timeout: Option<u32>,
+
+        /// Stop all detached containers of the configuration
+        #[clap(long, conflicts_with = "container")]
+        all: bool,
+
+        /// Container name
+        #[clap(required_unless_present = "all")]
+        container: Option<String>,
+    },
+
+    /// Send a signal to a running container
+    Kill {
+        /// Signal to send, i.e. `SIGINT` or `HUP`
+        #[clap(short, long, default_value = "SIGTERM", parse(try_from_str = parse_signal))]
+        signal: String,
+
+        /// Container name
+        container: String,
+    },
+
+    /// Show the logs of a running container
+    Logs {
+        /// Keep showing new output until interrupted
+        #[clap(short, long)]
+        follow: bool,
+
+        /// Number of lines to show from the end of the logs
+        #[clap(long)]
+        tail: Option<u32>,
+
+        /// Only show the output of this long ago, i.e. `10m` or `1h`
+        #[clap(long, parse(try_from_str = parse_duration))]
+        since: Option<Duration>,
+
+        /// Container name
+        container: String,
+    },
+
+    /// Start a new container with an interactive shell
+    Shell {
+        /// Platform to run the container on, i.e. `linux/arm64`
+        #[clap(long)]
+        platform: Option<Platform>,
+
+        /// Allow containers which are configured to run in privileged mode
+        #[clap(long)]
+        allow_privileged: bool,
+
+        /// Shell to start, defaults to `$SHELL`, `bash` or `sh`, whichever is available
+        #[clap(long)]
+        shell: Option<String>,
+
+        /// Container name
+        container: String,
+    },
+
+    /// Show the version and optionally check for a newer release
+    Version {
+        /// Check whether a newer release is available, at most once a day
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Diagnose common problems with the environment
+    Doctor {},
+
+    /// Show information about the runtime, directories and configuration
+    Info {},
+
     /// Remove cache and/or containers
     Clean {
-        /// Remove containers
+        /// Remove the containers started for the configuration, including running ones
         #[clap(short, long)]
         containers: bool,
+
+        /// Remove the networks created for the configuration
+        #[clap(short, long)]
+        networks: bool,
+
+        /// Only remove the script of this container
+        #[clap(long)]
+        container: Option<String>,
+
+        /// Only remove entries which have not been modified for this long, i.e. `7d` or `24h`
+        #[clap(long, parse(try_from_str = parse_duration))]
+        older_than: Option<Duration>,
+
+        /// Only remove the cached image data, keeping the scripts
+        #[clap(long, conflicts_with_all = &["containers", "networks", "container"])]
+        blobs_only: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, ArgEnum)]
+pub enum DebugFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum VolumeCommand {
+    /// Archive the contents of a volume
+    Backup {
+        /// Volume name
+        name: String,
+
+        /// Path of the gzip compressed tar archive to create
+        output: PathBuf,
+    },
+
+    /// Replace the contents of a volume with an archive
+    Restore {
+        /// Volume name
+        name: String,
+
+        /// Path of the gzip compressed tar archive to extract
+        input: PathBuf,
+    },
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+pub enum GenerateTarget {
+    /// Create a systemd user service which runs the container
+    Systemd {
+        /// Container name
+        container: String,
+
+        /// Unit file to create, defaults to `~/.config/systemd/user/<container>.service`
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
     },
 }
 
@@ -127,6 +543,45 @@ pub enum Shell {
         #[clap(flatten)]
         delegate: InjectShell,
     },
+
+    /// Configuration for nushell
+    ///
+    /// Nushell cannot source generated output directly, so store it
+    /// in a file first
+    ///
+    ///    toip inject nu [options] | save -f ~/.cache/toip/init.nu
+    ///
+    /// Then add the following to ~/.config/nushell/config.nu
+    ///
+    ///    source ~/.cache/toip/init.nu
+    ///
+    /// For example, to configure the $PATH variable
+    /// and to automatically install to containers;
+    /// generate the file with the following
+    ///
+    ///    toip inject nu --export-path --auto-install | save -f ~/.cache/toip/init.nu
+    #[clap(verbatim_doc_comment)]
+    Nu {
+        #[clap(flatten)]
+        delegate: InjectShell,
+    },
+
+    /// Configuration for elvish
+    ///
+    /// Add the following to ~/.elvish/rc.elv
+    ///
+    ///    eval (toip inject elvish [options] | slurp)
+    ///
+    /// For example, to configure the $PATH variable
+    /// and to automatically install to containers;
+    /// add the following
+    ///
+    ///    eval (toip inject elvish --export-path --auto-install | slurp)
+    #[clap(verbatim_doc_comment)]
+    Elvish {
+        #[clap(flatten)]
+        delegate: InjectShell,
+    },
 }
 #[derive(Debug, PartialEq, Args)]
 pub struct InjectShell {
@@ -142,3 +597,101 @@ pub struct InjectShell {
     #[clap(short = 'p', long)]
     pub auto_prepare: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn valid_definition() {
+        Cli::command().debug_assert();
+    }
+
+    fn run_args(arguments: &[&str]) -> Vec<String> {
+        let cli = Cli::try_parse_from(arguments).unwrap();
+        match cli.command {
+            Command::Run { args, .. } => args,
+            command => panic!("expected run, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn only_arguments_after_separator_are_passed() {
+        assert_eq!(
+            run_args(&["toip", "run", "./node", "--", "install", "--verbose"]),
+            vec!["install", "--verbose"]
+        );
+        assert!(run_args(&["toip", "run", "./node"]).is_empty());
+        assert!(Cli::try_parse_from(["toip", "run", "./node", "install"]).is_err());
+    }
+
+    #[test]
+    fn flags_before_separator_are_parsed() {
+        let cli =
+            Cli::try_parse_from(["toip", "-v", "run", "-d", "./node", "--", "-v", "--verbose"])
+                .unwrap();
+        assert_eq!(cli.verbose.log_level(), Some(log::Level::Warn));
+        match cli.command {
+            Command::Run { detach, args, .. } => {
+                assert!(detach);
+                assert_eq!(args, vec!["-v", "--verbose"]);
+            }
+            command => panic!("expected run, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn run_has_help() {
+        let error = Cli::try_parse_from(["toip", "run", "--help"]).unwrap_err();
+        assert_eq!(error.kind(), clap::ErrorKind::DisplayHelp);
+    }
+
+    #[test]
+    fn script_arguments_are_separated() {
+        let script = std::env::temp_dir().join(format!("toip-script-{}", std::process::id()));
+        std::fs::write(&script, "#!/usr/bin/toip run\nnode\n").unwrap();
+        let script = script.into_os_string();
+
+        let separated = script_arguments(
+            ["toip", "run"]
+                .iter()
+                .map(OsString::from)
+                .chain([script.clone(), "--help".into()]),
+        );
+        let explicit = script_arguments(["toip", "run"].iter().map(OsString::from).chain([
+            script.clone(),
+            "--".into(),
+            "-v".into(),
+        ]));
+        let missing = script_arguments(
+            ["toip", "run", "./missing", "--help"]
+                .iter()
+                .map(OsString::from),
+        );
+        std::fs::remove_file(&script).unwrap();
+
+        assert_eq!(
+            separated,
+            vec![
+                "toip".into(),
+                "run".into(),
+                script.clone(),
+                "--".into(),
+                "--help".into()
+            ]
+        );
+        assert_eq!(
+            explicit,
+            vec![
+                "toip".into(),
+                "run".into(),
+                script,
+                "--".into(),
+                "-v".into()
+            ]
+        );
+        assert_eq!(missing.len(), 4);
+    }
+}
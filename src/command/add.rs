@@ -0,0 +1,116 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_yaml::Value;
+
+use crate::command::init::prune;
+use crate::config::{
+    find_config_file, BuildSource, Config, ContainerConfig, RegistrySource, JSON_CONFIG_FILE_NAME,
+};
+
+pub fn parse_link(value: &str) -> Result<(String, String)> {
+    let (alias, container) = value
+        .split_once(':')
+        .ok_or_else(|| anyhow!("expected `ALIAS:CONTAINER`, got `{}`", value))?;
+    Ok((alias.to_string(), container.to_string()))
+}
+
+fn container_config(
+    image: Option<String>,
+    build_context: Option<PathBuf>,
+    links: Vec<(String, String)>,
+) -> Result<ContainerConfig> {
+    let mut container = ContainerConfig::default();
+    match (image, build_context) {
+        (Some(image), None) => {
+            let image = RegistrySource::try_from(image.as_str())
+                .with_context(|| format!("invalid image `{}`", image))?;
+            container.image = Some(image);
+        }
+        (None, Some(context)) => {
+            container.build = Some(BuildSource {
+                context,
+                ..Default::default()
+            });
+        }
+        _ => bail!("either `--image` or `--build-context` is required"),
+    }
+    container.links = links.into_iter().collect();
+
+    Ok(container)
+}
+
+/// Add the container to the configuration file, the file is edited as a document
+/// so profiles, defaults and extends are kept as they were written
+pub fn add(
+    name: String,
+    image: Option<String>,
+    build_context: Option<PathBuf>,
+    links: Vec<(String, String)>,
+    force: bool,
+) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let content = fs::read_to_string(&config_path).with_context(|| {
+        format!(
+            "could not read configuration file `{}`",
+            config_path.display()
+        )
+    })?;
+    let json = config_path.ends_with(JSON_CONFIG_FILE_NAME);
+
+    let mut document: Value = if json {
+        serde_json::from_str(&content).context("could not parse configuration")?
+    } else {
+        serde_yaml::from_str(&content).context("could not parse configuration")?
+    };
+    let mapping = match &mut document {
+        Value::Mapping(document) => document,
+        _ => bail!("configuration `{}` is not a mapping", config_path.display()),
+    };
+    let containers_key = Value::from("containers");
+    if !mapping.contains_key(&containers_key) {
+        mapping.insert(containers_key.clone(), Value::Mapping(Default::default()));
+    }
+    let containers = match mapping.get_mut(&containers_key) {
+        Some(Value::Mapping(containers)) => containers,
+        _ => bail!(
+            "`containers` in `{}` is not a mapping",
+            config_path.display()
+        ),
+    };
+
+    let key = Value::from(name.as_str());
+    if containers.contains_key(&key) && !force {
+        bail!(
+            "container `{}` already exists, use `--force` to overwrite it",
+            name
+        );
+    }
+
+    let container = container_config(image, build_context, links)?;
+    let value = serde_yaml::to_value(&container).context("could not serialize container")?;
+    let value = prune(value).unwrap_or_else(|| Value::Mapping(Default::default()));
+    // Remove the existing entry first, so the container is appended at the end
+    containers.remove(&key);
+    containers.insert(key, value);
+
+    let output = if json {
+        serde_json::to_string_pretty(&document).context("could not serialize configuration")?
+    } else {
+        serde_yaml::to_string(&document).context("could not serialize configuration")?
+    };
+
+    // Ensure the updated configuration can be read back
+    Config::new(output.as_bytes()).context("updated configuration is invalid")?;
+
+    fs::write(&config_path, output)
+        .with_context(|| format!("could not write to file `{}`", config_path.display()))?;
+    println!("added container `{}` to `{}`", name, config_path.display());
+
+    Ok(())
+}
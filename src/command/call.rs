@@ -1,14 +1,54 @@
 use std::collections::HashMap;
+use std::os::unix::fs::FileTypeExt;
 use std::os::unix::net::UnixStream;
 use std::path::Path;
+use std::{env, fs};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::join;
 use uds::UnixStreamExt;
 
 use crate::CallInfo;
 
-pub fn call<S, C, A>(socket_path: S, container: C, args: A) -> Result<()>
+/// Fail early with a clear error instead of the OS error of connecting to a missing socket
+fn check_socket(socket_path: &Path) -> Result<()> {
+    let metadata = match fs::metadata(socket_path) {
+        Ok(metadata) => metadata,
+        Err(_) => bail!(
+            "socket at `{}` does not exist; are you running inside a toip-managed container?",
+            socket_path.display()
+        ),
+    };
+    if !metadata.file_type().is_socket() {
+        bail!(
+            "`{}` is not a socket; are you running inside a toip-managed container?",
+            socket_path.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Values of the set variables, so the called container inherits them from the caller
+pub fn envargs<N>(names: &[N]) -> HashMap<String, String>
+where
+    N: AsRef<str>,
+{
+    names
+        .iter()
+        .filter_map(|name| {
+            let name = name.as_ref();
+            env::var(name).ok().map(|value| (name.to_string(), value))
+        })
+        .collect()
+}
+
+pub fn call<S, C, A>(
+    socket_path: S,
+    container: C,
+    args: A,
+    envargs: HashMap<String, String>,
+) -> Result<()>
 where
     S: AsRef<Path>,
     C: Into<String>,
@@ -17,10 +57,11 @@ where
     let call_info = CallInfo {
         name: container.into(),
         arguments: args.into_iter().collect(),
-        envargs: HashMap::new(),
+        envargs,
     };
 
     let socket_path = socket_path.as_ref();
+    check_socket(socket_path)?;
     let socket = UnixStream::connect(&socket_path)
         .with_context(|| format!("could not connect to socket `{}`", socket_path.display()))?;
 
@@ -0,0 +1,218 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::config::find_config_file;
+use crate::dirs;
+
+fn config_dir() -> Result<PathBuf> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+
+    Ok(config_path.parent().unwrap().to_path_buf())
+}
+
+/// Whether the entry was not modified within the given duration
+fn is_older(path: &Path, older_than: Option<Duration>) -> Result<bool> {
+    let older_than = match older_than {
+        Some(older_than) => older_than,
+        None => return Ok(true),
+    };
+
+    let modified = fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("could not read modification time of `{}`", path.display()))?;
+    let age = SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or_default();
+
+    Ok(age >= older_than)
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("could not remove `{}`", path.display()))?;
+    println!("removed `{}`", path.display());
+
+    Ok(())
+}
+
+fn clean_directory(directory: &Path, older_than: Option<Duration>) -> Result<()> {
+    if !directory.is_dir() {
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(directory)
+        .with_context(|| format!("could not read directory `{}`", directory.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if is_older(&path, older_than)? {
+            remove(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the cached state, only the script of a single container when it is given
+fn clean_state(
+    scripts_dir: &Path,
+    images_dir: &Path,
+    script: Option<&Path>,
+    older_than: Option<Duration>,
+    blobs_only: bool,
+) -> Result<()> {
+    match script {
+        Some(script) => {
+            if script.exists() && is_older(script, older_than)? {
+                remove(script)?;
+            }
+        }
+        None => {
+            if !blobs_only {
+                clean_directory(scripts_dir, older_than)?;
+            }
+            clean_directory(images_dir, older_than)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn clean(
+    containers: bool,
+    networks: bool,
+    container: Option<String>,
+    older_than: Option<Duration>,
+    blobs_only: bool,
+) -> Result<()> {
+    if containers {
+        let backend = Backend::new(
+            "docker",
+            "",
+            SupportedDriver::resolve_with_supported_binary()?,
+        );
+        for container in backend.remove_containers(config_dir()?).await? {
+            println!("removed container `{}`", container);
+        }
+    }
+
+    if networks {
+        let backend = Backend::new(
+            "docker",
+            "",
+            SupportedDriver::resolve_with_supported_binary()?,
+        );
+        for network in backend.remove_networks(config_dir()?).await? {
+            println!("removed network `{}`", network);
+        }
+    }
+
+    let script = match container {
+        Some(name) => Some(dirs::script(config_dir()?)?.join(&name)),
+        // Removing containers or networks leaves the cached state alone
+        None if containers || networks => return Ok(()),
+        None => None,
+    };
+
+    clean_state(
+        &dirs::scripts()?,
+        &dirs::images()?,
+        script.as_deref(),
+        older_than,
+        blobs_only,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::process;
+
+    use super::*;
+
+    /// Cache directories with an old and a new entry in both
+    fn state(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let root = env::temp_dir().join(format!("toip-clean-{}-{}", name, process::id()));
+        let scripts_dir = root.join("scripts");
+        let images_dir = root.join("images");
+        let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+        for dir in [&scripts_dir, &images_dir] {
+            fs::create_dir_all(dir).unwrap();
+            File::create(dir.join("old"))
+                .unwrap()
+                .set_modified(week_ago)
+                .unwrap();
+            File::create(dir.join("new")).unwrap();
+        }
+
+        (root, scripts_dir, images_dir)
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn removes_all_state() {
+        let (root, scripts_dir, images_dir) = state("all");
+        clean_state(&scripts_dir, &images_dir, None, None, false).unwrap();
+
+        assert!(remaining(&scripts_dir).is_empty());
+        assert!(remaining(&images_dir).is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn removes_only_the_script_of_the_container() {
+        let (root, scripts_dir, images_dir) = state("container");
+        clean_state(
+            &scripts_dir,
+            &images_dir,
+            Some(&scripts_dir.join("new")),
+            None,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(remaining(&scripts_dir), vec!["old"]);
+        assert_eq!(remaining(&images_dir), vec!["new", "old"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn keeps_entries_newer_than_the_age() {
+        let (root, scripts_dir, images_dir) = state("older-than");
+        let day = Duration::from_secs(24 * 60 * 60);
+        clean_state(&scripts_dir, &images_dir, None, Some(day), false).unwrap();
+
+        assert_eq!(remaining(&scripts_dir), vec!["new"]);
+        assert_eq!(remaining(&images_dir), vec!["new"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn keeps_scripts_when_only_removing_blobs() {
+        let (root, scripts_dir, images_dir) = state("blobs-only");
+        clean_state(&scripts_dir, &images_dir, None, None, true).unwrap();
+
+        assert_eq!(remaining(&scripts_dir), vec!["new", "old"]);
+        assert!(remaining(&images_dir).is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,88 @@
+use std::env;
+use std::iter;
+
+use anyhow::Result;
+use clap::CommandFactory;
+use itertools::Itertools;
+
+use crate::cli::Cli;
+use crate::config::{find_config_file, Config};
+
+/// Subcommands which take the name of a container as argument
+const CONTAINER_COMMANDS: [&str; 4] = ["exec", "kill", "logs", "shell"];
+
+fn print_containers() -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path = match find_config_file(current_dir) {
+        Some(config_path) => config_path,
+        None => return Ok(()),
+    };
+    let config = Config::new_from_file(&config_path)?;
+
+    let names = config
+        .containers
+        .iter()
+        .flat_map(|(name, container)| iter::once(name).chain(container.links.keys()))
+        .sorted()
+        .dedup();
+    for name in names {
+        println!("{}", name);
+    }
+
+    Ok(())
+}
+
+fn print_bash() -> Result<()> {
+    let current_exe = env::current_exe()?;
+    let subcommands = Cli::command()
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .join(" ");
+
+    print!(
+        r##"
+_toip_complete_containers() {{
+  "{}" completions --dynamic 2> /dev/null
+}}
+
+_toip() {{
+  local current="${{COMP_WORDS[COMP_CWORD]}}"
+  local previous="${{COMP_WORDS[COMP_CWORD-1]}}"
+  if [ "$COMP_CWORD" -eq 1 ]; then
+    COMPREPLY=($(compgen -W "{}" -- "$current"))
+    return
+  fi
+
+  if [ "$previous" = "-c" ] || [ "$previous" = "--container" ]; then
+    COMPREPLY=($(compgen -W "$(_toip_complete_containers)" -- "$current"))
+    return
+  fi
+
+  case "${{COMP_WORDS[1]}}" in
+    {})
+      COMPREPLY=($(compgen -W "$(_toip_complete_containers)" -- "$current"))
+      ;;
+    *)
+      COMPREPLY=($(compgen -f -- "$current"))
+      ;;
+  esac
+}}
+
+complete -F _toip toip
+"##,
+        current_exe.display(),
+        subcommands,
+        CONTAINER_COMMANDS.join("|")
+    );
+
+    Ok(())
+}
+
+/// Prints the bash completion script, or the configured container names when dynamic
+pub fn completions(dynamic: bool) -> Result<()> {
+    if dynamic {
+        print_containers()
+    } else {
+        print_bash()
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_derive::Serialize;
+
+use crate::backend::driver::SupportedDriver;
+use crate::cli::DebugFormat;
+use crate::command::validate::validate_config;
+use crate::config::{find_config_file, Config, Volume};
+use crate::dirs;
+
+#[derive(Serialize)]
+struct Dump {
+    config: Option<Config>,
+    resolved: BTreeMap<String, String>,
+    paths: BTreeMap<String, String>,
+    validation: Vec<String>,
+}
+
+fn insert_path<P>(paths: &mut BTreeMap<String, String>, name: &str, path: Result<P>)
+where
+    P: AsRef<Path>,
+{
+    let value = match path {
+        Ok(path) => path.as_ref().display().to_string(),
+        Err(error) => format!("unavailable: {:#}", error),
+    };
+    paths.insert(name.to_string(), value);
+}
+
+/// The values after substitution of the environment variables, keyed by their location
+fn resolved(config: &Config) -> BTreeMap<String, String> {
+    let mut resolved = BTreeMap::new();
+    for (name, container) in &config.containers {
+        for (key, value) in &container.env {
+            resolved.insert(
+                format!("containers.{}.env.{}", name, key),
+                value.clone().into_inner(),
+            );
+        }
+        for (index, path) in container.env_files.iter().enumerate() {
+            resolved.insert(
+                format!("containers.{}.env_files.{}", name, index),
+                path.as_ref().display().to_string(),
+            );
+        }
+        if let Some(path) = &container.seccomp_profile {
+            resolved.insert(
+                format!("containers.{}.seccomp_profile", name),
+                path.as_ref().display().to_string(),
+            );
+        }
+        if let Some(build) = &container.build {
+            for (key, value) in &build.build_args {
+                resolved.insert(
+                    format!("containers.{}.build.build_args.{}", name, key),
+                    value.clone().into_inner(),
+                );
+            }
+            for (kind, paths) in [("secrets", &build.secrets), ("ssh", &build.ssh)] {
+                for (key, path) in paths {
+                    resolved.insert(
+                        format!("containers.{}.build.{}.{}", name, kind, key),
+                        path.as_ref().display().to_string(),
+                    );
+                }
+            }
+        }
+    }
+    for (name, volume) in &config.volumes {
+        match volume {
+            Volume::Bind(bind) => {
+                resolved.insert(
+                    format!("volumes.{}.source", name),
+                    bind.source.as_ref().display().to_string(),
+                );
+            }
+            Volume::Anonymous(anonymous) => {
+                resolved.insert(
+                    format!("volumes.{}.name", name),
+                    anonymous.name.clone().into_inner(),
+                );
+            }
+            Volume::Tmpfs(_) => {}
+        }
+    }
+
+    resolved
+}
+
+pub fn debug(format: DebugFormat) -> Result<()> {
+    let mut paths = BTreeMap::new();
+    let mut validation = vec![];
+
+    for (name, path) in dirs::directories()? {
+        paths.insert(name.to_string(), path.display().to_string());
+    }
+    insert_path(&mut paths, "socket", dirs::socket_path());
+    insert_path(&mut paths, "bin", dirs::path());
+    insert_path(&mut paths, "global config", dirs::global_config());
+    insert_path(
+        &mut paths,
+        "driver",
+        SupportedDriver::resolve_with_supported_binary()
+            .map(|driver| driver.binary().to_path_buf()),
+    );
+
+    let current_dir = env::current_dir()?;
+    let config = match find_config_file(current_dir) {
+        None => {
+            validation.push("unable to find config file".to_string());
+            None
+        }
+        Some(config_path) => {
+            let config_dir = config_path.parent().unwrap().to_path_buf();
+            insert_path(&mut paths, "config file", Ok(&config_path));
+            insert_path(&mut paths, "scripts", dirs::script(&config_dir));
+
+            match Config::new_from_file(&config_path) {
+                Ok(config) => {
+                    validation.extend(validate_config(&config, &config_dir));
+                    Some(config)
+                }
+                Err(error) => {
+                    validation.push(format!("{:#}", error));
+                    None
+                }
+            }
+        }
+    };
+
+    let dump = Dump {
+        resolved: config.as_ref().map(resolved).unwrap_or_default(),
+        config,
+        paths,
+        validation,
+    };
+    let output = match format {
+        DebugFormat::Yaml => serde_yaml::to_string(&dump).context("could not serialize dump")?,
+        DebugFormat::Json => {
+            serde_json::to_string_pretty(&dump).context("could not serialize dump")?
+        }
+        // Convert to a value first, so plain values are written before the tables
+        DebugFormat::Toml => toml::Value::try_from(&dump)
+            .and_then(|value| toml::to_string_pretty(&value))
+            .context("could not serialize dump")?,
+    };
+    println!("{}", output.trim_end());
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+use crate::backend::Backend;
+use crate::config::{find_config_file, Config};
+use crate::dirs;
+
+#[derive(PartialEq)]
+enum Outcome {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Ok => write!(f, "[OK]  "),
+            Outcome::Warn => write!(f, "[WARN]"),
+            Outcome::Fail => write!(f, "[FAIL]"),
+        }
+    }
+}
+
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn check(&mut self, outcome: Outcome, message: String) {
+        if outcome == Outcome::Fail {
+            self.failures += 1;
+        }
+        println!("{} {}", outcome, message);
+    }
+}
+
+fn check_writable(directory: &Path) -> Result<()> {
+    dirs::create(directory)?;
+
+    let mut probe = directory.to_path_buf();
+    probe.push(".doctor");
+    File::create(&probe).with_context(|| format!("could not create file `{}`", probe.display()))?;
+    fs::remove_file(&probe)
+        .with_context(|| format!("could not remove file `{}`", probe.display()))?;
+
+    Ok(())
+}
+
+async fn check_runtime(report: &mut Report) -> Option<SupportedDriver> {
+    let driver = match SupportedDriver::resolve_with_supported_binary() {
+        Ok(driver) => driver,
+        Err(error) => {
+            report.check(Outcome::Fail, format!("container runtime: {}", error));
+            return None;
+        }
+    };
+    report.check(Outcome::Ok, "container runtime is installed".to_string());
+
+    match driver.list(false, &[]).await {
+        Ok(_) => report.check(Outcome::Ok, "container runtime is reachable".to_string()),
+        Err(error) => {
+            report.check(
+                Outcome::Fail,
+                format!("container runtime is not reachable: {:#}", error),
+            );
+            return None;
+        }
+    }
+
+    Some(driver)
+}
+
+fn check_directories(report: &mut Report) {
+    let directories = vec![
+        ("scripts", dirs::scripts()),
+        (
+            "socket",
+            dirs::socket_path().map(|socket| socket.parent().unwrap().to_path_buf()),
+        ),
+    ];
+
+    for (name, directory) in directories {
+        match directory.and_then(|directory| {
+            check_writable(&directory)?;
+            Ok(directory)
+        }) {
+            Ok(directory) => report.check(
+                Outcome::Ok,
+                format!("{} directory `{}` is writable", name, directory.display()),
+            ),
+            Err(error) => report.check(
+                Outcome::Fail,
+                format!("{} directory is not writable: {:#}", name, error),
+            ),
+        }
+    }
+}
+
+async fn check_config(report: &mut Report, driver: Option<SupportedDriver>) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path = match find_config_file(current_dir) {
+        Some(config_path) => config_path,
+        None => {
+            report.check(Outcome::Warn, "no configuration file found".to_string());
+            return Ok(());
+        }
+    };
+
+    let config_dir = config_path.parent().unwrap().to_path_buf();
+    let config = match Config::new_from_file(&config_path) {
+        Ok(config) => config,
+        Err(error) => {
+            report.check(
+                Outcome::Fail,
+                format!("configuration file is invalid: {:#}", error),
+            );
+            return Ok(());
+        }
+    };
+    report.check(
+        Outcome::Ok,
+        format!("configuration file `{}` is valid", config_path.display()),
+    );
+
+    let driver = match driver {
+        Some(driver) => driver,
+        None => return Ok(()),
+    };
+
+    let backend = Backend::new("docker", "", driver);
+    let mut names: Vec<_> = config.containers.keys().collect();
+    names.sort();
+    for name in names {
+        let container = &config.containers[name];
+        match backend.is_prepared(name, container, &config_dir).await {
+            Ok(true) => report.check(Outcome::Ok, format!("image of `{}` is prepared", name)),
+            Ok(false) => report.check(
+                Outcome::Warn,
+                format!(
+                    "image of `{}` is not prepared, run `toip prepare --container {}`",
+                    name, name
+                ),
+            ),
+            Err(error) => report.check(
+                Outcome::Fail,
+                format!("image of `{}` could not be inspected: {:#}", name, error),
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn doctor() -> Result<()> {
+    let mut report = Report { failures: 0 };
+
+    let driver = check_runtime(&mut report).await;
+
+    if env::var_os("TOIP_SOCK").is_some() {
+        report.check(
+            Outcome::Warn,
+            "environment variable `TOIP_SOCK` is set outside of a container".to_string(),
+        );
+    } else {
+        report.check(
+            Outcome::Ok,
+            "environment variable `TOIP_SOCK` is not set".to_string(),
+        );
+    }
+
+    check_directories(&mut report);
+    check_config(&mut report, driver).await?;
+
+    if report.failures > 0 {
+        bail!("{} check(s) failed", report.failures);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::{Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::{exit_code, load_project_config};
+
+/// Executes the command in the running container and returns the exit code of the command
+pub async fn exec(
+    container_name: String,
+    command: Vec<String>,
+    workdir: Option<PathBuf>,
+    global_config: bool,
+) -> Result<i32> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    config
+        .get_container_by_name(&container_name)
+        .with_context(|| {
+            format!(
+                "container with name `{}` does not exists in configuration",
+                container_name
+            )
+        })?;
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    )
+    .with_tty(io::stdin().is_terminal());
+    let status = backend
+        .exec(
+            &container_name,
+            &config_dir,
+            workdir,
+            command,
+            Stdio::inherit(),
+            Stdio::inherit(),
+            Stdio::inherit(),
+        )
+        .await
+        .with_context(|| format!("could not execute in container `{}`", container_name))?;
+
+    Ok(exit_code(status))
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_derive::{Deserialize, Serialize};
+use tar::{Builder, Header};
+
+use crate::config::{find_config_file, Config, Volume};
+use crate::lock::LOCK_FILE_NAME;
+use crate::metadata::VERSION;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.yaml";
+const DEFAULT_EXPORT_FILE_NAME: &str = "toip-export.tar.gz";
+
+/// Describes where an exported configuration originates from
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    pub origin: PathBuf,
+    pub platform: String,
+    /// Absolute host paths of the bind mounted volumes, these are not exported
+    #[serde(default)]
+    pub bind_mounts: BTreeMap<String, PathBuf>,
+}
+
+pub fn host_platform() -> String {
+    format!("{}/{}", env::consts::OS, env::consts::ARCH)
+}
+
+fn append_file(archive: &mut Builder<GzEncoder<File>>, path: &Path) -> Result<()> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("could not determine file name of `{}`", path.display()))?;
+    archive
+        .append_path_with_name(path, name)
+        .with_context(|| format!("could not archive `{}`", path.display()))
+}
+
+pub fn export(output: Option<PathBuf>) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap().to_path_buf();
+    let config = Config::new_from_file(&config_path)?;
+
+    let bind_mounts = config
+        .volumes
+        .iter()
+        .filter_map(|(name, volume)| match volume {
+            Volume::Bind(bind) => {
+                let source: &Path = bind.source.as_ref();
+                Some((name.clone(), config_dir.join(source)))
+            }
+            _ => None,
+        })
+        .collect();
+    let manifest = Manifest {
+        version: VERSION.to_string(),
+        origin: config_path.clone(),
+        platform: host_platform(),
+        bind_mounts,
+    };
+    let manifest = serde_yaml::to_string(&manifest).context("could not serialize manifest")?;
+
+    let output = output.unwrap_or_else(|| PathBuf::from(DEFAULT_EXPORT_FILE_NAME));
+    let file = File::create(&output)
+        .with_context(|| format!("could not create archive `{}`", output.display()))?;
+    let mut archive = Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let mut header = Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, MANIFEST_FILE_NAME, manifest.as_bytes())
+        .context("could not archive manifest")?;
+
+    append_file(&mut archive, &config_path)?;
+    let lock_path = config_dir.join(LOCK_FILE_NAME);
+    if lock_path.is_file() {
+        append_file(&mut archive, &lock_path)?;
+    }
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("could not write archive `{}`", output.display()))?;
+
+    println!(
+        "exported `{}` to `{}`",
+        config_path.display(),
+        output.display()
+    );
+
+    Ok(())
+}
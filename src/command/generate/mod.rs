@@ -0,0 +1,11 @@
+use anyhow::Result;
+
+use crate::cli::GenerateTarget;
+
+mod systemd;
+
+pub fn generate(target: GenerateTarget) -> Result<()> {
+    match target {
+        GenerateTarget::Systemd { container, output } => systemd::systemd(container, output),
+    }
+}
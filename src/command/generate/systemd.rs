@@ -0,0 +1,91 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::{find_config_file, Config};
+use crate::dirs;
+use crate::metadata::APPLICATION_NAME;
+
+const ENVIRONMENT_FILE_NAME: &str = "environment";
+
+/// Quote the argument when systemd would otherwise split it, `%` starts a specifier in units
+fn quote(path: &Path) -> String {
+    let path = path.display().to_string().replace('%', "%%");
+    if path.contains(char::is_whitespace) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path
+    }
+}
+
+fn unit(container: &str, exe: &Path, script: &Path, config_dir: &Path, env_file: &Path) -> String {
+    format!(
+        "[Unit]
+Description={application} container `{container}`
+After=network-online.target
+
+[Service]
+Type=simple
+WorkingDirectory={config_dir}
+ExecStart={exe} run {script}
+Restart=on-failure
+PassEnvironment=DISPLAY WAYLAND_DISPLAY XDG_RUNTIME_DIR
+EnvironmentFile=-{env_file}
+
+[Install]
+WantedBy=default.target
+",
+        application = APPLICATION_NAME,
+        container = container,
+        config_dir = quote(config_dir),
+        exe = quote(exe),
+        script = quote(script),
+        env_file = quote(env_file),
+    )
+}
+
+pub fn systemd(container: String, output: Option<PathBuf>) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap().to_path_buf();
+    let config = Config::new_from_file(&config_path)?;
+    config
+        .get_container_by_name(&container)
+        .with_context(|| format!("No container name `{}`", container))?;
+
+    let script = dirs::script(&config_dir)?.join(&container);
+    if !script.exists() {
+        log::warn!(
+            "run script `{}` does not exist yet, run `{} install` first",
+            script.display(),
+            APPLICATION_NAME
+        );
+    }
+    let exe = env::current_exe().context("could not determine current executable")?;
+
+    let output = match output {
+        Some(output) => output,
+        None => dirs::systemd_units()?.join(format!("{}.service", container)),
+    };
+    // Drop-in directory of the unit, the environment file in it is optional
+    let mut override_dir = output.clone().into_os_string();
+    override_dir.push(".d");
+    let override_dir = PathBuf::from(override_dir);
+    dirs::create(&override_dir)?;
+    let env_file = override_dir.join(ENVIRONMENT_FILE_NAME);
+
+    let unit = unit(&container, &exe, &script, &config_dir, &env_file);
+    fs::write(&output, unit)
+        .with_context(|| format!("could not write service unit `{}`", output.display()))?;
+
+    println!("{}", output.display());
+    log::info!(
+        "run `systemctl --user daemon-reload`, variables for the container can be set in `{}`",
+        env_file.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::GzDecoder;
+use serde_yaml::Value;
+use tar::Archive;
+
+use crate::command::export::{host_platform, Manifest, MANIFEST_FILE_NAME};
+use crate::command::init::write_config;
+use crate::command::version::is_newer;
+use crate::config::{
+    AnonymousVolume, BindVolume, BuildSource, Config, ContainerConfig, HostPort, Port,
+    RegistrySource, Volume, CONFIG_FILE_NAME, JSON_CONFIG_FILE_NAME,
+};
+use crate::lock::LOCK_FILE_NAME;
+use crate::metadata::VERSION;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ARCHIVE_FILE_NAMES: [&str; 3] = [CONFIG_FILE_NAME, JSON_CONFIG_FILE_NAME, LOCK_FILE_NAME];
+
+const COMPOSE_FILE_NAMES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+const SUPPORTED_KEYS: [&str; 8] = [
+    "image",
+    "build",
+    "environment",
+    "volumes",
+    "ports",
+    "entrypoint",
+    "command",
+    "working_dir",
+];
+
+pub fn find_compose_file(dir: &Path) -> Option<PathBuf> {
+    COMPOSE_FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        _ => None,
+    }
+}
+
+fn as_strings(value: &Value) -> Result<Vec<String>> {
+    match value {
+        Value::String(string) => Ok(string.split_whitespace().map(String::from).collect()),
+        Value::Sequence(sequence) => sequence
+            .iter()
+            .map(|item| as_string(item).ok_or_else(|| anyhow!("expected a string")))
+            .collect(),
+        _ => bail!("expected a string or a list of strings"),
+    }
+}
+
+fn convert_build(value: &Value) -> Result<BuildSource> {
+    if let Some(context) = as_string(value) {
+        return Ok(BuildSource {
+            context: context.into(),
+            ..Default::default()
+        });
+    }
+
+    let context = value
+        .get("context")
+        .and_then(as_string)
+        .unwrap_or_else(|| ".".to_string());
+    let mut build_args = HashMap::new();
+    match value.get("args") {
+        Some(Value::Mapping(mapping)) => {
+            for (key, value) in mapping.iter() {
+                if let (Some(key), Some(value)) = (as_string(key), as_string(value)) {
+                    build_args.insert(key, value.into());
+                }
+            }
+        }
+        Some(Value::Sequence(sequence)) => {
+            for item in sequence.iter().filter_map(as_string) {
+                if let Some((key, value)) = item.split_once('=') {
+                    build_args.insert(key.to_string(), value.to_string().into());
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(BuildSource {
+        context: PathBuf::from(&context),
+        file: value
+            .get("dockerfile")
+            .and_then(as_string)
+            .map(|file| Path::new(&context).join(file)),
+        target: value.get("target").and_then(as_string),
+        build_args,
+        ..Default::default()
+    })
+}
+
+fn convert_environment(value: &Value, container: &mut ContainerConfig) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping.iter() {
+                if let Some(key) = as_string(key) {
+                    match as_string(value) {
+                        Some(value) => {
+                            container.env.insert(key, value.into());
+                        }
+                        None => container.inherit_envvars.push(key),
+                    }
+                }
+            }
+        }
+        Value::Sequence(sequence) => {
+            for item in sequence.iter().filter_map(as_string) {
+                match item.split_once('=') {
+                    Some((key, value)) => {
+                        container
+                            .env
+                            .insert(key.to_string(), value.to_string().into());
+                    }
+                    None => container.inherit_envvars.push(item),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn convert_port(value: &Value) -> Result<Port> {
+    if let Value::Mapping(_) = value {
+        let container = value
+            .get("target")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow!("port is missing `target`"))?;
+        let host = match value.get("published").and_then(as_string) {
+            Some(published) => HostPort::Specified(published.parse()?),
+            None => HostPort::Generated,
+        };
+        return Ok(Port {
+            host,
+            container: u16::try_from(container)?,
+        });
+    }
+
+    let port = as_string(value).ok_or_else(|| anyhow!("expected a port mapping"))?;
+    // Strip the protocol, only tcp is supported
+    let port = port.split('/').next().unwrap_or_default();
+    let parts: Vec<&str> = port.rsplitn(3, ':').collect();
+    let (host, container) = match parts.as_slice() {
+        [container] => (HostPort::Generated, *container),
+        [container, host, ..] => (HostPort::Specified(host.parse()?), *container),
+        [] => bail!("empty port mapping"),
+    };
+
+    Ok(Port {
+        host,
+        container: container
+            .parse()
+            .with_context(|| format!("unsupported port `{}`", port))?,
+    })
+}
+
+fn convert_volume(
+    service: &str,
+    value: &Value,
+    volumes: &mut HashMap<String, Volume>,
+) -> Result<(PathBuf, String)> {
+    let (source, target, readonly) = match value {
+        Value::Mapping(_) => (
+            value.get("source").and_then(as_string),
+            value
+                .get("target")
+                .and_then(as_string)
+                .ok_or_else(|| anyhow!("volume is missing `target`"))?,
+            value
+                .get("read_only")
+                .and_then(Value::as_bool)
+                .unwrap_or(false),
+        ),
+        _ => {
+            let volume = as_string(value).ok_or_else(|| anyhow!("expected a volume"))?;
+            let parts: Vec<&str> = volume.split(':').collect();
+            match parts.as_slice() {
+                [target] => (None, target.to_string(), false),
+                [source, target] => (Some(source.to_string()), target.to_string(), false),
+                [source, target, mode] => (
+                    Some(source.to_string()),
+                    target.to_string(),
+                    mode.split(',').any(|option| option == "ro"),
+                ),
+                _ => bail!("unsupported volume `{}`", volume),
+            }
+        }
+    };
+
+    let name = match source {
+        Some(source) if source.starts_with('.') || source.starts_with('/') => {
+            let name = format!("{}-{}", service, volumes.len());
+            volumes.insert(
+                name.clone(),
+                Volume::Bind(BindVolume {
+                    source: PathBuf::from(source).into(),
+                    readonly,
+                    consistency: None,
+                    propagation: None,
+                    non_recursive: None,
+                }),
+            );
+            name
+        }
+        Some(source) => {
+            volumes.entry(source.clone()).or_insert_with(|| {
+                Volume::Anonymous(AnonymousVolume {
+                    name: source.clone().into(),
+                    external: false,
+                })
+            });
+            source
+        }
+        None => {
+            let name = format!("{}-{}", service, volumes.len());
+            volumes.insert(
+                name.clone(),
+                Volume::Anonymous(AnonymousVolume {
+                    name: name.clone().into(),
+                    external: false,
+                }),
+            );
+            name
+        }
+    };
+
+    Ok((PathBuf::from(target), name))
+}
+
+fn convert_service(
+    name: &str,
+    service: &Value,
+    volumes: &mut HashMap<String, Volume>,
+) -> Result<ContainerConfig> {
+    let mut container = ContainerConfig::default();
+
+    if let Value::Mapping(mapping) = service {
+        for key in mapping.iter().filter_map(|(key, _)| key.as_str()) {
+            if !SUPPORTED_KEYS.contains(&key) {
+                log::warn!(
+                    "service `{}` uses unsupported compose feature `{}`, it is ignored",
+                    name,
+                    key
+                );
+            }
+        }
+    }
+
+    if let Some(image) = service.get("image").and_then(as_string) {
+        container.image = Some(RegistrySource::try_from(image.as_str())?);
+    }
+    if let Some(build) = service.get("build") {
+        container.build = Some(convert_build(build).context("could not convert `build`")?);
+    }
+    if let Some(environment) = service.get("environment") {
+        convert_environment(environment, &mut container);
+    }
+    if let Some(Value::Sequence(ports)) = service.get("ports") {
+        for port in ports {
+            match convert_port(port) {
+                Ok(port) => container.ports.push(port),
+                Err(error) => log::warn!("service `{}`: skipping port: {:#}", name, error),
+            }
+        }
+    }
+    if let Some(Value::Sequence(service_volumes)) = service.get("volumes") {
+        for volume in service_volumes {
+            let (target, volume_name) = convert_volume(name, volume, volumes)
+                .with_context(|| format!("could not convert volume of service `{}`", name))?;
+            container.volumes.insert(target, volume_name);
+        }
+    }
+    if let Some(entrypoint) = service.get("entrypoint") {
+        let mut entrypoint = as_strings(entrypoint).context("could not convert `entrypoint`")?;
+        if !entrypoint.is_empty() {
+            container.entrypoint = Some(entrypoint.remove(0));
+            container.args.extend(entrypoint);
+        }
+    }
+    if let Some(command) = service.get("command") {
+        let mut command = as_strings(command).context("could not convert `command`")?;
+        if !command.is_empty() {
+            container.cmd = Some(command.remove(0));
+            container.args.extend(command);
+        }
+    }
+    container.workdir = service
+        .get("working_dir")
+        .and_then(as_string)
+        .map(PathBuf::from);
+
+    Ok(container)
+}
+
+/// Convert the services of a docker compose file into a configuration
+pub fn import_compose(path: &Path) -> Result<Config> {
+    let file = File::open(path)
+        .with_context(|| format!("could not read compose file `{}`", path.display()))?;
+    let compose: Value = serde_yaml::from_reader(file)
+        .with_context(|| format!("could not parse compose file `{}`", path.display()))?;
+
+    if compose.get("networks").is_some() {
+        log::warn!("compose networks are not supported, they are ignored");
+    }
+
+    let services = match compose.get("services") {
+        Some(Value::Mapping(services)) => services,
+        _ => bail!("compose file `{}` contains no services", path.display()),
+    };
+
+    let mut containers = HashMap::new();
+    let mut volumes = HashMap::new();
+    for (name, service) in services.iter() {
+        let name = as_string(name).ok_or_else(|| anyhow!("invalid service name"))?;
+        let container = convert_service(&name, service, &mut volumes)
+            .with_context(|| format!("could not convert service `{}`", name))?;
+        containers.insert(name, container);
+    }
+
+    Ok(Config {
+        containers,
+        volumes,
+        networks: HashMap::new(),
+        profiles: HashMap::new(),
+        defaults: None,
+    })
+}
+
+fn is_archive(file: &Path) -> Result<bool> {
+    let mut magic = [0; 2];
+    let mut reader =
+        File::open(file).with_context(|| format!("could not open `{}`", file.display()))?;
+    match reader.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == GZIP_MAGIC),
+        Err(_) => Ok(false),
+    }
+}
+
+/// Extract a configuration created by `toip export` into the current directory
+fn open_archive(file: &Path) -> Result<Archive<GzDecoder<File>>> {
+    let archive_file =
+        File::open(file).with_context(|| format!("could not open `{}`", file.display()))?;
+    Ok(Archive::new(GzDecoder::new(archive_file)))
+}
+
+/// Reads the manifest and the names of the configuration files without extracting anything
+fn read_archive(file: &Path) -> Result<(Manifest, Vec<String>)> {
+    let mut archive = open_archive(file)?;
+
+    let mut manifest = None;
+    let mut names = vec![];
+    for entry in archive
+        .entries()
+        .with_context(|| format!("could not read archive `{}`", file.display()))?
+    {
+        let mut entry = entry.context("could not read archive entry")?;
+        let name = entry.path()?.to_string_lossy().to_string();
+
+        if name == MANIFEST_FILE_NAME {
+            let parsed: Manifest =
+                serde_yaml::from_reader(&mut entry).context("could not parse manifest")?;
+            manifest = Some(parsed);
+        } else if ARCHIVE_FILE_NAMES.contains(&name.as_str()) {
+            names.push(name);
+        } else {
+            log::warn!("skipping unexpected file `{}` in archive", name);
+        }
+    }
+
+    let manifest = manifest.ok_or_else(|| anyhow!("archive contains no manifest"))?;
+    Ok((manifest, names))
+}
+
+fn import_archive(file: &Path, force: bool) -> Result<()> {
+    let config_dir = env::current_dir()?;
+
+    // Validate the archive before extracting, so a rejected archive leaves no files behind
+    let (manifest, names) = read_archive(file)?;
+    if is_newer(&manifest.version, VERSION) {
+        bail!(
+            "archive was exported by version `{}`, which is newer than version `{}`",
+            manifest.version,
+            VERSION
+        );
+    }
+    if !force {
+        if let Some(target) = names
+            .iter()
+            .map(|name| config_dir.join(name))
+            .find(|target| target.exists())
+        {
+            bail!(
+                "file `{}` already exists, use `--force` to overwrite it",
+                target.display()
+            );
+        }
+    }
+
+    let mut archive = open_archive(file)?;
+    let mut imported = vec![];
+    for entry in archive
+        .entries()
+        .with_context(|| format!("could not read archive `{}`", file.display()))?
+    {
+        let mut entry = entry.context("could not read archive entry")?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        if !names.contains(&name) {
+            continue;
+        }
+
+        let target = config_dir.join(&name);
+        entry
+            .unpack(&target)
+            .with_context(|| format!("could not extract `{}`", target.display()))?;
+        imported.push(target);
+    }
+
+    if manifest.platform != host_platform() {
+        log::warn!(
+            "configuration was exported on `{}`, images might not be available for `{}`",
+            manifest.platform,
+            host_platform()
+        );
+    }
+    for (name, path) in &manifest.bind_mounts {
+        if !path.exists() {
+            log::warn!(
+                "bind mounted volume `{}` pointed to `{}`, which does not exist here",
+                name,
+                path.display()
+            );
+        }
+    }
+
+    for path in imported {
+        println!("imported `{}`", path.display());
+    }
+
+    Ok(())
+}
+
+pub fn import(file: PathBuf, force: bool) -> Result<()> {
+    if is_archive(&file)? {
+        return import_archive(&file, force);
+    }
+
+    let config_dir = file
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let config_path = config_dir.join(CONFIG_FILE_NAME);
+    if config_path.exists() && !force {
+        bail!(
+            "configuration file `{}` already exists, use `--force` to overwrite it",
+            config_path.display()
+        );
+    }
+
+    let config = import_compose(&file)?;
+    write_config(&config, &config_path)
+}
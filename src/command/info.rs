@@ -0,0 +1,108 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+use crate::config::{find_config_file, Config};
+use crate::dirs;
+
+const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+fn human_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn directory_size(path: &Path) -> Result<u64> {
+    let metadata = fs::symlink_metadata(path)
+        .with_context(|| format!("could not read metadata of `{}`", path.display()))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)
+        .with_context(|| format!("could not read directory `{}`", path.display()))?
+    {
+        let entry = entry?;
+        size += directory_size(&entry.path())?;
+    }
+
+    Ok(size)
+}
+
+fn count_entries(path: &Path) -> Result<usize> {
+    if !path.is_dir() {
+        return Ok(0);
+    }
+
+    let count = fs::read_dir(path)
+        .with_context(|| format!("could not read directory `{}`", path.display()))?
+        .count();
+    Ok(count)
+}
+
+pub async fn info() -> Result<()> {
+    match SupportedDriver::resolve_with_supported_binary() {
+        Ok(driver) => {
+            let version = driver
+                .version()
+                .await
+                .unwrap_or_else(|error| format!("unknown ({:#})", error));
+            println!("runtime:     {} ({})", driver.binary().display(), version);
+        }
+        Err(error) => println!("runtime:     not available ({})", error),
+    }
+
+    println!();
+    println!("directories:");
+    for (name, directory) in dirs::directories()? {
+        let state = if directory.is_dir() {
+            human_size(directory_size(&directory)?)
+        } else {
+            "missing".to_string()
+        };
+        println!("  {:<10} {} ({})", name, directory.display(), state);
+    }
+
+    println!();
+    let scripts = dirs::scripts()?;
+    println!(
+        "installed:   {} script directories",
+        count_entries(&scripts)?
+    );
+    let volumes = dirs::volumes_dir()?;
+    let volume_size = if volumes.is_dir() {
+        directory_size(&volumes)?
+    } else {
+        0
+    };
+    println!("volumes:     {}", human_size(volume_size));
+
+    println!();
+    let current_dir = env::current_dir()?;
+    match find_config_file(current_dir) {
+        Some(config_path) => {
+            println!("config:      {}", config_path.display());
+            match Config::new_from_file(&config_path) {
+                Ok(config) => println!("containers:  {}", config.containers.len()),
+                Err(error) => println!("containers:  invalid configuration ({:#})", error),
+            }
+        }
+        None => println!("config:      none"),
+    }
+
+    Ok(())
+}
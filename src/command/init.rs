@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+
+use crate::backend::BUILD_FILE_NAMES;
+use crate::command::import::{find_compose_file, import_compose};
+use crate::config::{BuildSource, Config, ContainerConfig, RegistrySource, CONFIG_FILE_NAME};
+
+fn prompt(question: &str, default: Option<&str>) -> Result<Option<String>> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush().context("could not write to stdout")?;
+
+    let mut answer = String::new();
+    let read = io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("could not read from stdin")?;
+    // Without input, questions requiring an answer would be asked forever
+    if read == 0 {
+        println!();
+        bail!("no answer to `{}`, standard input is closed", question);
+    }
+
+    let answer = answer.trim();
+    if answer.is_empty() {
+        Ok(default.map(|default| default.to_string()))
+    } else {
+        Ok(Some(answer.to_string()))
+    }
+}
+
+fn confirm(question: &str) -> Result<bool> {
+    let answer = prompt(&format!("{} (y/N)", question), None)?;
+    Ok(matches!(
+        answer.as_deref(),
+        Some("y") | Some("Y") | Some("yes")
+    ))
+}
+
+fn prompt_container(current_dir: &Path) -> Result<ContainerConfig> {
+    let has_build_file = BUILD_FILE_NAMES
+        .iter()
+        .any(|name| current_dir.join(name).is_file());
+    let default_context = if has_build_file { Some(".") } else { None };
+
+    let mut container = ContainerConfig::default();
+    match prompt(
+        "Build context (leave empty to use an image)",
+        default_context,
+    )? {
+        Some(context) => {
+            container.build = Some(BuildSource {
+                context: PathBuf::from(context),
+                ..Default::default()
+            });
+        }
+        None => loop {
+            if let Some(image) = prompt("Image", None)? {
+                match RegistrySource::try_from(image.as_str()) {
+                    Ok(image) => {
+                        container.image = Some(image);
+                        break;
+                    }
+                    Err(error) => eprintln!("{}", error),
+                }
+            }
+        },
+    }
+
+    container.cmd = prompt("Command (optional)", None)?;
+    container.workdir = prompt("Working directory (optional)", None)?.map(PathBuf::from);
+
+    Ok(container)
+}
+
+/// Remove empty values so the generated file only contains what was configured
+pub fn prune(value: Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Mapping(mapping) => {
+            let pruned: serde_yaml::Mapping = mapping
+                .into_iter()
+                .filter_map(|(key, value)| prune(value).map(|value| (key, value)))
+                .collect();
+            if pruned.is_empty() {
+                None
+            } else {
+                Some(Value::Mapping(pruned))
+            }
+        }
+        Value::Sequence(sequence) if sequence.is_empty() => None,
+        Value::Bool(false) => None,
+        value => Some(value),
+    }
+}
+
+/// Write the configuration to the path, leaving out all unset values
+pub fn write_config(config: &Config, config_path: &Path) -> Result<()> {
+    let value = serde_yaml::to_value(config).context("could not serialize configuration")?;
+    let yaml = serde_yaml::to_string(&prune(value).unwrap_or(Value::Null))
+        .context("could not serialize configuration")?;
+
+    // Ensure the generated configuration can be read back
+    Config::new(yaml.as_bytes()).context("generated configuration is invalid")?;
+
+    fs::write(config_path, yaml)
+        .with_context(|| format!("could not write to file `{}`", config_path.display()))?;
+    println!("created configuration file `{}`", config_path.display());
+
+    Ok(())
+}
+
+pub fn init(force: bool) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path = current_dir.join(CONFIG_FILE_NAME);
+    if config_path.exists() && !force {
+        bail!(
+            "configuration file `{}` already exists, use `--force` to overwrite it",
+            config_path.display()
+        );
+    }
+
+    if let Some(compose_file) = find_compose_file(&current_dir) {
+        if confirm(&format!(
+            "Import the services from `{}`?",
+            compose_file.display()
+        ))? {
+            let config = import_compose(&compose_file)?;
+            return write_config(&config, &config_path);
+        }
+    }
+
+    let default_name = current_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string());
+
+    let mut containers = HashMap::new();
+    loop {
+        let name = match prompt("Container name", default_name.as_deref())? {
+            Some(name) => name,
+            None => continue,
+        };
+        let container = prompt_container(&current_dir)?;
+        containers.insert(name, container);
+
+        if !confirm("Add another container?")? {
+            break;
+        }
+    }
+
+    if containers.len() > 1 && confirm("Link the containers to each other?")? {
+        let names: Vec<String> = containers.keys().cloned().collect();
+        for (name, container) in containers.iter_mut() {
+            container.links = names
+                .iter()
+                .filter(|other| *other != name)
+                .map(|other| (other.clone(), other.clone()))
+                .collect();
+        }
+    }
+
+    let config = Config {
+        containers,
+        volumes: HashMap::new(),
+        networks: HashMap::new(),
+        profiles: HashMap::new(),
+        defaults: None,
+    };
+    write_config(&config, &config_path)?;
+
+    Ok(())
+}
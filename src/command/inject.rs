@@ -43,6 +43,13 @@ export PROMPT_COMMAND="_toip_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}"
     Ok(())
 }
 
+fn print_bash_completions() -> Result<()> {
+    let current_exe = env::current_exe()?;
+    println!("source <(\"{}\" completions)", current_exe.display());
+
+    Ok(())
+}
+
 fn print_fish(export_path: bool, auto_install: bool, auto_prepare: bool) -> Result<()> {
     if export_path {
         let path = dirs::path()?;
@@ -73,9 +80,80 @@ function _toip_hook --on-variable PWD {{
     Ok(())
 }
 
+fn print_nu(export_path: bool, auto_install: bool, auto_prepare: bool) -> Result<()> {
+    if export_path {
+        let path = dirs::path()?;
+        println!("$env.PATH = ($env.PATH | prepend \"{}\")", path.display());
+    }
+
+    let mut calls = Vec::new();
+    let current_exe = env::current_exe()?;
+    let current_exe = current_exe.display();
+    if auto_install {
+        calls.push(format!("^\"{}\" install --ignore-missing", &current_exe));
+    }
+    if auto_prepare {
+        calls.push(format!("^\"{}\" prepare --ignore-missing", &current_exe));
+    }
+
+    if !calls.is_empty() {
+        print!(
+            r##"
+$env.config = ($env.config | upsert hooks.env_change.PWD {{|config|
+  let hooks = ($config | get -i hooks.env_change.PWD | default [])
+  $hooks | append {{|before, after|
+{}
+  }}
+}})
+"##,
+            calls.iter().map(|l| format!("    {}", l)).join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+fn print_elvish(export_path: bool, auto_install: bool, auto_prepare: bool) -> Result<()> {
+    if export_path {
+        let path = dirs::path()?;
+        println!("set paths = ['{}' $@paths]", path.display());
+    }
+
+    let mut calls = Vec::new();
+    let current_exe = env::current_exe()?;
+    let current_exe = current_exe.display();
+    if auto_install {
+        calls.push(format!("'{}' install --ignore-missing", &current_exe));
+    }
+    if auto_prepare {
+        calls.push(format!("'{}' prepare --ignore-missing", &current_exe));
+    }
+
+    if !calls.is_empty() {
+        print!(
+            r##"
+set after-chdir = [$@after-chdir {{|dir|
+{}
+}}]
+"##,
+            calls.iter().map(|l| format!("    {}", l)).join("\n")
+        );
+    }
+
+    Ok(())
+}
+
 pub fn inject(shell: Shell) -> Result<()> {
     match shell {
-        Shell::Bash { delegate } | Shell::Zsh { delegate } => print_bash_compatible(
+        Shell::Bash { delegate } => {
+            print_bash_compatible(
+                delegate.export_path,
+                delegate.auto_install,
+                delegate.auto_prepare,
+            )?;
+            print_bash_completions()
+        }
+        Shell::Zsh { delegate } => print_bash_compatible(
             delegate.export_path,
             delegate.auto_install,
             delegate.auto_prepare,
@@ -85,5 +163,15 @@ pub fn inject(shell: Shell) -> Result<()> {
             delegate.auto_install,
             delegate.auto_prepare,
         ),
+        Shell::Nu { delegate } => print_nu(
+            delegate.export_path,
+            delegate.auto_install,
+            delegate.auto_prepare,
+        ),
+        Shell::Elvish { delegate } => print_elvish(
+            delegate.export_path,
+            delegate.auto_install,
+            delegate.auto_prepare,
+        ),
     }
 }
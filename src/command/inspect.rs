@@ -0,0 +1,45 @@
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::config::{find_config_file, Config};
+
+/// Print the resolved configuration of the container as JSON, optionally a single field of it
+pub fn inspect(
+    container_name: String,
+    raw: bool,
+    field: Option<String>,
+    global_config: bool,
+) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config = Config::new_from_file(&config_path)?.with_global(global_config)?;
+
+    let container = config
+        .get_container_by_name(&container_name)
+        .with_context(|| {
+            format!(
+                "container with name `{}` does not exists in configuration",
+                container_name
+            )
+        })?;
+
+    let value = serde_json::to_value(&container).context("could not serialize container")?;
+    let value = match &field {
+        Some(pointer) => value
+            .pointer(pointer)
+            .ok_or_else(|| anyhow!("container `{}` has no field `{}`", container_name, pointer))?,
+        None => &value,
+    };
+
+    let output = if raw {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+    .context("could not serialize container")?;
+    println!("{}", output);
+
+    Ok(())
+}
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::os::unix::fs as unix_fs;
 use std::path::{Path, PathBuf};
 use std::{env, fs};
@@ -6,6 +5,7 @@ use std::{env, fs};
 use anyhow::{anyhow, bail, Context, Result};
 
 use crate::backend::script;
+use crate::command::run::load_config_file;
 use crate::config::Config;
 use crate::{config, dirs};
 
@@ -68,7 +68,38 @@ where
     })
 }
 
-pub fn install(ignore_missing_config: bool) -> Result<()> {
+/// What an install would do, without touching the disk
+fn plan(config_file: &Path, script_dir: &Path, lookup_dir: &Path, config: &Config) -> Vec<String> {
+    let mut steps = vec![];
+    if script_dir.exists() {
+        steps.push(format!(
+            "would reset scripts directory `{}`",
+            script_dir.display()
+        ));
+    }
+    let mut container_names: Vec<&String> = config.containers.keys().collect();
+    container_names.sort();
+    for container_name in container_names {
+        steps.push(format!(
+            "would create run script `{}`",
+            script_dir.join(container_name).display()
+        ));
+    }
+    steps.push(format!(
+        "would copy configuration file `{}` to `{}`",
+        config_file.display(),
+        script_dir.display()
+    ));
+    steps.push(format!(
+        "would point scripts lookup directory `{}` to `{}`",
+        lookup_dir.display(),
+        script_dir.display()
+    ));
+
+    steps
+}
+
+pub fn install(ignore_missing_config: bool, dry_run: bool, global_config: bool) -> Result<()> {
     let current_dir = env::current_dir()?;
 
     let config_path = config::find_config_file(current_dir);
@@ -76,7 +107,15 @@ pub fn install(ignore_missing_config: bool) -> Result<()> {
     match config_path {
         None => {
             let empty = Path::new("/dev/null");
-            modify_lookup(&empty).context("could not modify container lookup directory")?;
+            if dry_run {
+                log::info!(
+                    "would point scripts lookup directory `{}` to `{}`",
+                    dirs::path()?.display(),
+                    empty.display()
+                );
+            } else {
+                modify_lookup(&empty).context("could not modify container lookup directory")?;
+            }
             if ignore_missing_config {
                 Ok(())
             } else {
@@ -84,16 +123,7 @@ pub fn install(ignore_missing_config: bool) -> Result<()> {
             }
         }
         Some(file) => {
-            let config_file = File::open(&file).with_context(|| {
-                format!(
-                    "could not open config file `{}` for reading",
-                    file.display()
-                )
-            })?;
-
-            let config = Config::new(config_file).with_context(|| {
-                format!("could not create config from file `{}`", file.display())
-            })?;
+            let config = load_config_file(&file, global_config)?;
 
             // Parent directory always exists because a file always
             // exists within a directory
@@ -101,6 +131,13 @@ pub fn install(ignore_missing_config: bool) -> Result<()> {
 
             let script_dir = dirs::script(&config_dir)?;
 
+            if dry_run {
+                for step in plan(&file, &script_dir, &dirs::path()?, &config) {
+                    log::info!("{}", step);
+                }
+                return Ok(());
+            }
+
             if script_dir.exists() {
                 // Reset whole directory
                 fs::remove_dir_all(&script_dir).with_context(|| {
@@ -139,3 +176,52 @@ pub fn install(ignore_missing_config: bool) -> Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn dry_run_plans_without_touching_the_disk() {
+        let root = env::temp_dir().join(format!("toip-install-{}", process::id()));
+        let script_dir = root.join("scripts");
+        let lookup_dir = root.join("bin");
+        let config = Config::new(
+            "containers:\n  node:\n    image: node:18\n  php:\n    image: php:8\n".as_bytes(),
+        )
+        .unwrap();
+
+        let steps = plan(
+            Path::new("/project/toip.yaml"),
+            &script_dir,
+            &lookup_dir,
+            &config,
+        );
+
+        assert_eq!(
+            steps,
+            vec![
+                format!(
+                    "would create run script `{}`",
+                    script_dir.join("node").display()
+                ),
+                format!(
+                    "would create run script `{}`",
+                    script_dir.join("php").display()
+                ),
+                format!(
+                    "would copy configuration file `/project/toip.yaml` to `{}`",
+                    script_dir.display()
+                ),
+                format!(
+                    "would point scripts lookup directory `{}` to `{}`",
+                    lookup_dir.display(),
+                    script_dir.display()
+                ),
+            ]
+        );
+        assert!(!root.exists());
+    }
+}
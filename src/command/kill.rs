@@ -0,0 +1,25 @@
+use anyhow::{Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::load_project_config;
+
+pub async fn kill(container_name: String, signal: String, global_config: bool) -> Result<()> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    config
+        .get_container_by_name(&container_name)
+        .with_context(|| {
+            format!(
+                "container with name `{}` does not exists in configuration",
+                container_name
+            )
+        })?;
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+    backend.kill(&container_name, &config_dir, &signal).await
+}
@@ -0,0 +1,55 @@
+use std::env;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::config::{find_config_file, Config};
+use crate::lock::Lock;
+
+pub async fn lock(update: bool, global_config: bool) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap();
+    let config = Config::new_from_file(&config_path)?.with_global(global_config)?;
+
+    let mut lock = if update {
+        Lock::default()
+    } else {
+        Lock::load(config_dir)?
+    };
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+    let mut containers: Vec<_> = config.containers.into_iter().collect();
+    containers.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+    for (name, container) in containers {
+        if container.image.is_none() {
+            log::debug!("skipping container `{}` as it is built locally", name);
+            continue;
+        }
+        if lock.containers.contains_key(&name) {
+            continue;
+        }
+
+        backend
+            .pull(&name, &container, config_dir)
+            .await
+            .with_context(|| format!("could not pull container `{}`", name))?;
+
+        match backend.digest(&name, &container, config_dir).await? {
+            Some((repository, digest)) => {
+                println!("Locked `{}` to `{}@{}`", name, repository, digest);
+                lock.record(&name, &repository, digest);
+            }
+            None => log::warn!("image of container `{}` has no digest to lock", name),
+        }
+    }
+
+    lock.save(config_dir)
+}
@@ -0,0 +1,13 @@
+use anyhow::{Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+
+pub async fn login(registry: String) -> Result<()> {
+    let driver = SupportedDriver::resolve_with_supported_binary()
+        .context("no container runtime available")?;
+
+    driver
+        .login(&registry)
+        .await
+        .with_context(|| format!("could not login to registry `{}`", registry))
+}
@@ -0,0 +1,16 @@
+use anyhow::{Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+
+pub async fn logout(registry: String) -> Result<()> {
+    let driver = SupportedDriver::resolve_with_supported_binary()
+        .context("no container runtime available")?;
+
+    driver
+        .logout(&registry)
+        .await
+        .with_context(|| format!("could not logout from registry `{}`", registry))?;
+    println!("removed credentials for `{}`", registry);
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::signal;
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::{exit_code, load_project_config};
+
+/// Shows the logs of the running container, when following until interrupted
+pub async fn logs(
+    container_name: String,
+    follow: bool,
+    tail: Option<u32>,
+    since: Option<Duration>,
+    global_config: bool,
+) -> Result<i32> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    config
+        .get_container_by_name(&container_name)
+        .with_context(|| {
+            format!(
+                "container with name `{}` does not exists in configuration",
+                container_name
+            )
+        })?;
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+    let logs = backend.logs(
+        &container_name,
+        &config_dir,
+        follow,
+        tail,
+        since,
+        Stdio::inherit(),
+        Stdio::inherit(),
+    );
+
+    tokio::select! {
+        status = logs => {
+            let status = status
+                .with_context(|| format!("could not show logs of container `{}`", container_name))?;
+            Ok(exit_code(status))
+        }
+        _ = signal::ctrl_c() => Ok(0),
+    }
+}
@@ -1,11 +1,65 @@
+mod add;
 mod call;
+mod clean;
+mod completions;
+mod debug;
+mod doctor;
+mod exec;
+mod export;
+mod generate;
+mod import;
+mod info;
+mod init;
 mod inject;
+mod inspect;
 mod install;
+mod kill;
+mod lock;
+mod login;
+mod logout;
+mod logs;
 mod prepare;
+mod ps;
+mod pull;
 mod run;
+mod shell;
+mod status;
+mod stop;
+mod template;
+mod update;
+mod validate;
+mod version;
+mod volume;
 
-pub use call::call;
+pub use add::{add, parse_link};
+pub use call::{call, envargs};
+pub use clean::clean;
+pub use completions::completions;
+pub use debug::debug;
+pub use doctor::doctor;
+pub use exec::exec;
+pub use export::export;
+pub use generate::generate;
+pub use import::import;
+pub use info::info;
+pub use init::init;
 pub use inject::inject;
+pub use inspect::inspect;
 pub use install::install;
+pub use kill::kill;
+pub use lock::lock;
+pub use login::login;
+pub use logout::logout;
+pub use logs::logs;
 pub use prepare::prepare;
+pub use ps::ps;
+pub use pull::pull;
 pub use run::run;
+pub use shell::shell;
+pub use status::status;
+pub use stop::stop;
+pub use template::{parse_key_value, template};
+pub use update::update;
+pub use validate::validate;
+pub use version::version;
+pub use volume::volume;
@@ -1,20 +1,57 @@
 use std::env;
-use std::fs::File;
 use std::path::Path;
+use std::time::Instant;
 
 use anyhow::{bail, Context, Result};
+use serde_derive::Serialize;
 
-use crate::backend::driver::DockerCliCompatible;
-use crate::backend::Backend;
-use crate::config::{find_config_file, Config};
+use crate::backend::driver::{Driver, SupportedDriver};
+use crate::backend::progress::Progress;
+use crate::backend::{Backend, PrepareAction};
+use crate::command::run::load_config_file;
+use crate::config::{find_config_file, Config, Platform};
+use crate::lock::Lock;
 
-async fn prepare_config(
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Pulled,
+    Built,
+    Skipped,
+    Failed,
+}
+
+impl From<PrepareAction> for Action {
+    fn from(action: PrepareAction) -> Self {
+        match action {
+            PrepareAction::Pulled => Action::Pulled,
+            PrepareAction::Built => Action::Built,
+            PrepareAction::Skipped => Action::Skipped,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Outcome {
+    container: String,
+    action: Action,
+    duration_ms: u128,
+}
+
+/// Prepare the containers, a failure only ends the run when the outcomes are not collected
+async fn prepare_containers<D>(
+    backend: &Backend<D>,
     config: &Config,
     container: Option<String>,
     config_path: &Path,
-) -> Result<()> {
-    let backend = Backend::<DockerCliCompatible>::default();
-    match container {
+    json: bool,
+    dry_run: bool,
+    lock: &mut Lock,
+) -> Result<Vec<Outcome>>
+where
+    D: Driver + Sync,
+{
+    let mut containers = match container {
         Some(name) => {
             let container = config
                 .get_container_by_name(name.as_str())
@@ -24,25 +61,140 @@ async fn prepare_config(
                         name
                     )
                 })?;
-            backend
-                .prepare(&name, &container, config_path)
+            vec![(name, container)]
+        }
+        None => config.containers.clone().into_iter().collect(),
+    };
+    containers.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+    let mut outcomes = vec![];
+    for (name, container) in containers {
+        if dry_run {
+            let (repository, reference) = backend.image_name(&name, &container, config_path)?;
+            if container.build.is_some() {
+                log::info!(
+                    "would build image `{}:{}` for container `{}`",
+                    repository,
+                    reference,
+                    name
+                );
+            } else {
+                log::info!(
+                    "would pull image `{}:{}` for container `{}`",
+                    repository,
+                    reference,
+                    name
+                );
+            }
+            for link in container.links.keys() {
+                log::info!("would link `{}` to container `{}`", link, name);
+            }
+            for network in &container.networks {
+                log::info!("would create network `{}` if missing", network);
+            }
+            continue;
+        }
+
+        let start = Instant::now();
+        let result = match backend.prepare(&name, &container, config_path).await {
+            Ok(action) => backend
+                .create_networks(config, &container, config_path)
                 .await
-                .with_context(|| format!("could not prepare container `{}`", name))?;
+                .map(|_| action),
+            Err(error) => Err(error),
         }
-        None => {
-            for (name, container) in &config.containers {
-                backend
-                    .prepare(name, container, config_path)
-                    .await
-                    .with_context(|| format!("could not prepare container `{}`", name))?;
+        .with_context(|| format!("could not prepare container `{}`", name));
+
+        if result.is_ok() && Lock::exists(config_path) {
+            match backend.digest(&name, &container, config_path).await {
+                Ok(Some((repository, digest))) => lock.record(&name, &repository, digest),
+                Ok(None) => log::debug!("image of container `{}` has no digest to lock", name),
+                Err(error) => {
+                    log::warn!("could not lock image of container `{}`: {:#}", name, error)
+                }
             }
         }
-    };
+
+        if !json {
+            result?;
+            continue;
+        }
+
+        let action = match result {
+            Ok(action) => action.into(),
+            Err(error) => {
+                log::error!("{:#}", error);
+                Action::Failed
+            }
+        };
+        outcomes.push(Outcome {
+            container: name,
+            action,
+            duration_ms: start.elapsed().as_millis(),
+        });
+    }
+
+    Ok(outcomes)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn prepare_config(
+    config: &Config,
+    container: Option<String>,
+    config_path: &Path,
+    json: bool,
+    dry_run: bool,
+    progress: Progress,
+    no_cache: bool,
+    lock: &mut Lock,
+) -> Result<()> {
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    )
+    .with_progress(progress)
+    .with_no_cache(no_cache);
+    let outcomes = prepare_containers(
+        &backend,
+        config,
+        container,
+        config_path,
+        json,
+        dry_run,
+        lock,
+    )
+    .await?;
+
+    if json {
+        let output =
+            serde_json::to_string(&outcomes).context("could not serialize prepare summary")?;
+        println!("{}", output);
+
+        let failures = outcomes
+            .iter()
+            .filter(|outcome| matches!(outcome.action, Action::Failed))
+            .count();
+        if failures > 0 {
+            bail!("could not prepare {} container(s)", failures);
+        }
+    }
 
     Ok(())
 }
 
-pub async fn prepare(ignore_missing_config: bool, container: Option<String>) -> Result<()> {
+#[allow(clippy::too_many_arguments)]
+pub async fn prepare(
+    ignore_missing_config: bool,
+    container: Option<String>,
+    platform: Option<Platform>,
+    allow_privileged: bool,
+    json: bool,
+    dry_run: bool,
+    progress: Progress,
+    no_cache: bool,
+    global_config: bool,
+) -> Result<()> {
     let current_dir = env::current_dir()?;
     let config_path = find_config_file(current_dir);
 
@@ -55,18 +207,140 @@ pub async fn prepare(ignore_missing_config: bool, container: Option<String>) ->
             }
         }
         Some(file) => {
-            let config_file = File::open(&file).with_context(|| {
-                format!(
-                    "could not open config file `{}` for reading",
-                    file.display()
-                )
-            })?;
+            let mut config = load_config_file(&file, global_config)?;
+            if let Some(platform) = &platform {
+                config.override_platform(platform);
+            }
+            config.check_privileged(allow_privileged)?;
 
-            let config = Config::new(config_file).with_context(|| {
-                format!("could not create config from file `{}`", file.display())
-            })?;
+            let config_dir = file.parent().unwrap();
+            let mut lock = Lock::load(config_dir)?;
+
+            let result = prepare_config(
+                &config, container, config_dir, json, dry_run, progress, no_cache, &mut lock,
+            )
+            .await;
+            // Only keep an existing lock up to date, `toip lock` creates it
+            if !dry_run && Lock::exists(config_dir) {
+                lock.save(config_dir)?;
+            }
 
-            prepare_config(&config, container, file.parent().unwrap()).await
+            result
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use serde_json::Value;
+
+    use super::*;
+    use crate::backend::driver::mock::MockDriver;
+    use crate::dirs;
+
+    const CONFIG: &str = "containers:
+  app:
+    image: alpine:3
+  offline:
+    image: alpine:3
+    pull_policy: never
+";
+
+    fn config_dir(name: &str) -> std::path::PathBuf {
+        let config_dir = env::temp_dir().join(format!("toip-prepare-{}-{}", name, process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        config_dir
+    }
+
+    #[tokio::test]
+    async fn json_reports_the_outcome_of_every_container() {
+        let config_dir = config_dir("json");
+        let driver = MockDriver::default();
+        let pulled = driver.pulled.clone();
+        let backend = Backend::new("docker", "", driver);
+        let config = Config::new(CONFIG.as_bytes()).unwrap();
+
+        let outcomes = prepare_containers(
+            &backend,
+            &config,
+            None,
+            &config_dir,
+            true,
+            false,
+            &mut Lock::default(),
+        )
+        .await
+        .unwrap();
+        let _ = fs::remove_dir_all(dirs::image("docker", &config_dir).unwrap());
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        let summary = serde_json::to_value(&outcomes).unwrap();
+        let fields: Vec<(&str, &str)> = summary
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|outcome| {
+                assert!(outcome["duration_ms"].is_u64());
+                (
+                    outcome["container"].as_str().unwrap(),
+                    outcome["action"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(fields, vec![("app", "pulled"), ("offline", "failed")]);
+        assert_eq!(*pulled.lock().unwrap(), vec!["docker.io/library/alpine:3"]);
+    }
+
+    #[tokio::test]
+    async fn failure_ends_the_run_without_json() {
+        let config_dir = config_dir("failure");
+        let backend = Backend::new("docker", "", MockDriver::default());
+        let config = Config::new(CONFIG.as_bytes()).unwrap();
+
+        let result = prepare_containers(
+            &backend,
+            &config,
+            Some("offline".into()),
+            &config_dir,
+            false,
+            false,
+            &mut Lock::default(),
+        )
+        .await;
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn dry_run_prepares_nothing() {
+        let config_dir = config_dir("dry-run");
+        let driver = MockDriver::default();
+        let pulled = driver.pulled.clone();
+        let backend = Backend::new("docker", "", driver);
+        let config = Config::new(CONFIG.as_bytes()).unwrap();
+
+        let outcomes = prepare_containers(
+            &backend,
+            &config,
+            None,
+            &config_dir,
+            true,
+            true,
+            &mut Lock::default(),
+        )
+        .await
+        .unwrap();
+        let image_dir = dirs::image("docker", &config_dir).unwrap();
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&outcomes).unwrap(),
+            Value::Array(vec![])
+        );
+        assert!(pulled.lock().unwrap().is_empty());
+        assert!(!image_dir.exists());
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::load_project_config;
+
+pub async fn ps(all: bool, global_config: bool) -> Result<()> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    let driver = match SupportedDriver::resolve_with_supported_binary() {
+        Ok(driver) => driver,
+        Err(error) => {
+            println!("no container runtime available: {}", error);
+            return Ok(());
+        }
+    };
+    let backend = Backend::new("docker", "", driver);
+
+    let mut names: Vec<_> = config.containers.keys().collect();
+    names.sort();
+
+    let mut rows = vec![];
+    for name in names {
+        let instances = match backend.running(name, &config_dir, all).await {
+            Ok(instances) => instances,
+            Err(error) => {
+                println!("container runtime is not reachable: {:#}", error);
+                return Ok(());
+            }
+        };
+        rows.extend(instances.into_iter().map(|instance| (name, instance)));
+    }
+
+    println!(
+        "{:<14} {:<20} {:<24} {:<40} {:<24} {:<20} PORTS",
+        "CONTAINER ID", "CONTAINER", "NAME", "IMAGE", "STATUS", "CREATED"
+    );
+    for (name, instance) in rows {
+        println!(
+            "{:<14} {:<20} {:<24} {:<40} {:<24} {:<20} {}",
+            instance.id.chars().take(12).collect::<String>(),
+            name,
+            instance.name,
+            instance.image,
+            instance.status,
+            instance.running_for,
+            instance.ports
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+use crate::backend::Backend;
+use crate::command::run::load_config_file;
+use crate::config::{find_config_file, Config, Platform};
+
+pub async fn pull(
+    ignore_missing_config: bool,
+    container: Option<String>,
+    platform: Option<Platform>,
+    global_config: bool,
+) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path = match find_config_file(current_dir) {
+        Some(config_path) => config_path,
+        None => {
+            if ignore_missing_config {
+                return Ok(());
+            } else {
+                bail!("Missing config file");
+            }
+        }
+    };
+
+    let mut config = load_config_file(&config_path, global_config)?;
+    if let Some(platform) = &platform {
+        config.override_platform(platform);
+    }
+
+    let config_dir = config_path.parent().unwrap();
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+    pull_containers(&backend, config, container, config_dir).await
+}
+
+/// Pull or build the images only, the scripts of the containers are left alone
+async fn pull_containers<D>(
+    backend: &Backend<D>,
+    config: Config,
+    container: Option<String>,
+    config_dir: &Path,
+) -> Result<()>
+where
+    D: Driver + Sync,
+{
+    let containers = match container {
+        Some(name) => {
+            let container = config
+                .get_container_by_name(name.as_str())
+                .with_context(|| {
+                    format!(
+                        "container with name `{}` does not exists in configuration",
+                        name
+                    )
+                })?;
+            vec![(name, container)]
+        }
+        None => config.containers.into_iter().collect(),
+    };
+
+    for (name, container) in containers {
+        backend
+            .pull(&name, &container, config_dir)
+            .await
+            .with_context(|| format!("could not pull container `{}`", name))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::backend::driver::mock::MockDriver;
+    use crate::dirs;
+
+    #[tokio::test]
+    async fn pulls_images_without_creating_scripts() {
+        let config_dir = env::temp_dir().join(format!("toip-pull-{}", process::id()));
+        let driver = MockDriver::default();
+        let pulled = driver.pulled.clone();
+        let backend = Backend::new("docker", "", driver);
+        let config = Config::new(
+            "containers:\n  node:\n    image: node:18\n    links:\n      php: php\n  php:\n    image: php:8\n"
+                .as_bytes(),
+        )
+        .unwrap();
+
+        pull_containers(&backend, config, Some("node".into()), &config_dir)
+            .await
+            .unwrap();
+
+        assert_eq!(*pulled.lock().unwrap(), vec!["docker.io/library/node:18"]);
+        assert!(!dirs::image("docker", &config_dir).unwrap().exists());
+        assert!(!dirs::script(&config_dir).unwrap().exists());
+    }
+}
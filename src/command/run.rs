@@ -1,27 +1,281 @@
-use std::os::unix::io::FromRawFd;
-use std::path::Path;
-use std::process::Stdio;
-use std::{env, fs};
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::os::unix::io::{BorrowedFd, FromRawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use std::{env, fs, io};
 
 use anyhow::{anyhow, Context, Result};
 use futures_util::stream::FuturesUnordered;
 use itertools::join;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
 use tokio::sync::mpsc;
+use tokio::{signal, time};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
 
-use crate::backend::driver::DockerCliCompatible;
+use crate::backend::driver::SupportedDriver;
 use crate::backend::{script, Backend};
-use crate::command::call::call;
-use crate::config::{find_config_file, Config};
+use crate::command::call::{call, envargs};
+use crate::config::{find_config_file, Config, Platform, Volume};
+use crate::lock::Lock;
 use crate::{dirs, server};
 
-pub async fn run<P>(script_path: P, args: Vec<String>) -> Result<()>
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match status.code() {
+        Some(code) => code,
+        // Follow the shell convention for processes terminated by a signal
+        None => status.signal().map_or(1, |signal| 128 + signal),
+    }
+}
+
+/// Loads the configuration of the current directory merged with the global configuration,
+/// with the locked digests applied
+pub fn load_project_config(global_config: bool) -> Result<(PathBuf, PathBuf, Config)> {
+    // TODO decide how to load config
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap().to_path_buf();
+    let config = load_config_file(&config_path, global_config)?;
+
+    Ok((config_path, config_dir, config))
+}
+
+/// Loads the configuration file merged with the global configuration, with its args files
+/// resolved and the locked digests applied
+pub fn load_config_file(config_path: &Path, global_config: bool) -> Result<Config> {
+    let mut config = Config::new_from_file(config_path)?
+        .with_global(global_config)
+        .context("could not merge global config")?;
+    // A configuration file always exists within a directory
+    Lock::load(config_path.parent().unwrap())?.apply(&mut config);
+
+    Ok(config)
+}
+
+pub fn load_config(
+    platform: &Option<Platform>,
+    allow_privileged: bool,
+    global_config: bool,
+) -> Result<(PathBuf, PathBuf, Config)> {
+    let (config_path, config_dir, mut config) = load_project_config(global_config)?;
+    if let Some(platform) = platform {
+        config.override_platform(platform);
+    }
+    config.check_privileged(allow_privileged)?;
+
+    Ok((config_path, config_dir, config))
+}
+
+fn resolve(config_dir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        config_dir.join(path)
+    }
+}
+
+/// The configuration file, build contexts and bind mounted sources
+fn watch_paths(config_path: &Path, config_dir: &Path, config: &Config) -> Vec<PathBuf> {
+    let mut paths = vec![config_path.to_path_buf()];
+    for container in config.containers.values() {
+        if let Some(build) = &container.build {
+            paths.push(resolve(config_dir, &build.context));
+        }
+    }
+    for volume in config.volumes.values() {
+        if let Volume::Bind(bind) = volume {
+            paths.push(resolve(config_dir, bind.source.as_ref()));
+        }
+    }
+
+    paths.retain(|path| path.exists());
+    paths
+}
+
+/// Containers of which a changed path is in the build context
+fn changed_builds<'a>(config_dir: &Path, config: &'a Config, changed: &[PathBuf]) -> Vec<&'a str> {
+    let mut names: Vec<&str> = config
+        .containers
+        .iter()
+        .filter(|(_, container)| match &container.build {
+            Some(build) => {
+                let context = resolve(config_dir, &build.context);
+                changed.iter().any(|path| path.starts_with(&context))
+            }
+            None => false,
+        })
+        .map(|(name, _)| name.as_str())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+/// Wait until any of the paths changed, returns `None` when interrupted
+async fn wait_for_change(paths: &[PathBuf]) -> Result<Option<Vec<PathBuf>>> {
+    let (tx, mut rx) = mpsc::channel(100);
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+        if let Ok(event) = result {
+            if !matches!(event.kind, EventKind::Access(_)) {
+                let _ = tx.blocking_send(event.paths);
+            }
+        }
+    })
+    .context("could not create file watcher")?;
+    for path in paths {
+        watcher
+            .watch(path, RecursiveMode::Recursive)
+            .with_context(|| format!("could not watch `{}`", path.display()))?;
+    }
+
+    let mut changed = tokio::select! {
+        Some(changed) = rx.recv() => changed,
+        _ = signal::ctrl_c() => return Ok(None),
+    };
+
+    // Collect the events of partial writes until no changes occur for a while
+    loop {
+        tokio::select! {
+            Some(paths) = rx.recv() => changed.extend(paths),
+            _ = time::sleep(WATCH_DEBOUNCE) => break,
+            _ = signal::ctrl_c() => return Ok(None),
+        }
+    }
+
+    Ok(Some(changed))
+}
+
+/// Starts the container in the background without a call listener, so its links cannot be called
+async fn run_detached(
+    script_path: &Path,
+    args: Vec<String>,
+    platform: &Option<Platform>,
+    allow_privileged: bool,
+    global_config: bool,
+) -> Result<i32> {
+    let container_name = script::read_container(script_path)
+        .with_context(|| format!("could not read script file `{}`", script_path.display()))?;
+    let (_, config_dir, config) = load_config(platform, allow_privileged, global_config)?;
+    let container_config = config
+        .get_container_by_name(&container_name)
+        .with_context(|| format!("No container name `{}`", container_name))?;
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    )
+    .with_detach(true);
+    let (status, name) = backend
+        .spawn(
+            &config,
+            &container_name,
+            &container_config,
+            &config_dir,
+            args,
+            HashMap::new(),
+            Stdio::null(),
+            Stdio::null(),
+            Stdio::inherit(),
+        )
+        .await
+        .with_context(|| format!("could not start container `{}`", container_name))?;
+    if status.success() {
+        println!("{}", name);
+    }
+
+    Ok(exit_code(status))
+}
+
+/// Runs the container and returns the exit code of the origin container,
+/// when watching, the container is started again whenever its sources change
+#[allow(clippy::too_many_arguments)]
+pub async fn run<P>(
+    script_path: P,
+    args: Vec<String>,
+    platform: Option<Platform>,
+    allow_privileged: bool,
+    watch: bool,
+    detach: bool,
+    global_config: bool,
+) -> Result<i32>
 where
     P: AsRef<Path>,
 {
     let script_path = script_path.as_ref();
+    if detach {
+        return run_detached(
+            script_path,
+            args,
+            &platform,
+            allow_privileged,
+            global_config,
+        )
+        .await;
+    }
+    if !watch {
+        return run_once(
+            script_path,
+            args,
+            &platform,
+            allow_privileged,
+            global_config,
+        )
+        .await;
+    }
+
+    loop {
+        let code = run_once(
+            script_path,
+            args.clone(),
+            &platform,
+            allow_privileged,
+            global_config,
+        )
+        .await?;
+        log::info!(
+            "container exited with code `{}`, watching for changes",
+            code
+        );
+
+        let (config_path, config_dir, config) =
+            load_config(&platform, allow_privileged, global_config)?;
+        let paths = watch_paths(&config_path, &config_dir, &config);
+        let changed = match wait_for_change(&paths).await? {
+            Some(changed) => changed,
+            None => return Ok(code),
+        };
+
+        // The configuration might have changed as well
+        let (_, config_dir, config) = load_config(&platform, allow_privileged, global_config)?;
+        let backend = Backend::new(
+            "docker",
+            "",
+            SupportedDriver::resolve_with_supported_binary()?,
+        );
+        for name in changed_builds(&config_dir, &config, &changed) {
+            log::info!("build context of container `{}` changed, rebuilding", name);
+            backend
+                .prepare(name, &config.containers[name], &config_dir)
+                .await
+                .with_context(|| format!("could not prepare container `{}`", name))?;
+        }
+    }
+}
+
+async fn run_once(
+    script_path: &Path,
+    args: Vec<String>,
+    platform: &Option<Platform>,
+    allow_privileged: bool,
+    global_config: bool,
+) -> Result<i32> {
     let container_name = script::read_container(script_path)
         .with_context(|| format!("could not read script file `{}`", script_path.display()))?;
 
@@ -32,13 +286,32 @@ where
         )
     })?;
 
-    // TODO decide how to load config
-    let current_dir = env::current_dir()?;
-    let config_path =
-        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
-    let config_dir = config_path.parent().unwrap().to_path_buf();
-    let config = Config::new_from_dir(&config_dir)?;
+    let (_, config_dir, config) = load_config(platform, allow_privileged, global_config)?;
 
+    serve(container_name, args, config_dir, config).await
+}
+
+/// Whether the error was caused by lacking permissions or a read-only file system
+fn is_not_writable(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|error| {
+            matches!(
+                error.kind(),
+                io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+            )
+        })
+}
+
+/// Listens for calls and starts the containers until the origin container exits,
+/// returns the exit code of the origin container
+pub async fn serve(
+    container_name: String,
+    args: Vec<String>,
+    config_dir: PathBuf,
+    config: Config,
+) -> Result<i32> {
     let (tx, rx) = mpsc::channel(100);
 
     // Start listening for incoming calls
@@ -53,10 +326,22 @@ where
     fs::create_dir_all(socket_dir)
         .with_context(|| format!("could not create directory `{}`", socket_dir.display()))?;
     let serve_socket = socket.clone();
-    let server = server::create(serve_socket, tx, cancellation_token.clone())
-        .context("could not setup call listener")?;
+    let server = server::create(serve_socket, tx, cancellation_token.clone()).map_err(|error| {
+        if is_not_writable(&error) {
+            anyhow!(
+                "socket directory `{}` is not writable, use `--socket-path` or `TOIP_SOCK_DIR` to select another location",
+                socket_dir.display()
+            )
+        } else {
+            error.context("could not setup call listener")
+        }
+    })?;
 
     // Call the setup listener to start the initial container
+    let envargs = match config.get_container_by_name(&container_name) {
+        Some(container_config) => envargs(&container_config.inherit_envvars),
+        None => HashMap::new(),
+    };
     let call_socket = socket.clone();
     let origin_container_name = &container_name.clone();
     let call_handle = tokio::spawn(async move {
@@ -65,7 +350,7 @@ where
             &container_name,
             args.join(", ")
         );
-        call(&call_socket, &container_name, args)
+        call(&call_socket, &container_name, args, envargs)
             .with_context(|| format!("could not call container `{}`", container_name))
     });
     let server_handle = tokio::spawn(async move {
@@ -99,7 +384,15 @@ where
         let container_handle = tokio::spawn(async move {
             log::debug!("received call for container `{}`", instruction.info.name);
 
-            let backend = Backend::new("docker", call_socket, DockerCliCompatible::default());
+            // The caller's input is a terminal when the call was made from a terminal
+            let tty =
+                unsafe { BorrowedFd::borrow_raw(instruction.file_descriptors[0]) }.is_terminal();
+            let backend = Backend::new(
+                "docker",
+                call_socket,
+                SupportedDriver::resolve_with_supported_binary()?,
+            )
+            .with_tty(tty);
             let name = &instruction.info.name;
             let container_option = config.get_container_by_name(name);
             let container_config =
@@ -119,11 +412,13 @@ where
                         &container_config,
                         &config_dir,
                         instruction.info.arguments,
+                        instruction.info.envargs,
                         stdin,
                         stdout,
                         stderr,
                     )
                     .await
+                    .map(|(status, _)| status)
             }
         });
 
@@ -149,11 +444,14 @@ where
         }
     }
 
+    let mut code = 0;
     if let Some(handle) = cancellation_handle {
-        handle
+        let status = handle
             .await
             .context("could not join cancellation thread")?
             .context("failure during cancellation thread")?;
+        code = exit_code(status);
+        log::debug!("origin container exited with code `{}`", code);
     }
 
     log::debug!("Instruction stream ended");
@@ -175,5 +473,129 @@ where
     }
     log::debug!("All containers threads finished executing");
 
-    Ok(())
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_writable_errors() {
+        let denied = anyhow::Error::new(io::Error::from(io::ErrorKind::PermissionDenied))
+            .context("could not listen on socket");
+        assert!(is_not_writable(&denied));
+
+        let missing = anyhow::Error::new(io::Error::from(io::ErrorKind::NotFound))
+            .context("could not listen on socket");
+        assert!(!is_not_writable(&missing));
+    }
+
+    #[test]
+    fn args_file_is_appended_to_args() {
+        let config_dir = env::temp_dir().join(format!("toip-args-file-{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+        let config_path = config_dir.join("toip.yaml");
+        fs::write(
+            &config_path,
+            "containers:\n  app:\n    image: alpine:3\n    args: [--yaml]\n    args_file: app.args\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("app.args"),
+            "--first\n# skipped\n  --second  \n\n--third\n",
+        )
+        .unwrap();
+
+        let config = load_config_file(&config_path, false);
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert_eq!(
+            config.unwrap().containers["app"].args,
+            vec!["--yaml", "--first", "--second", "--third"]
+        );
+    }
+
+    const WATCHED_CONFIG: &str = "containers:
+  api:
+    build: api
+  web:
+    build: web
+  db:
+    image: postgres:15
+volumes:
+  sources:
+    type: bind
+    source: src
+  missing:
+    type: bind
+    source: missing
+";
+
+    fn watched_project(name: &str) -> (PathBuf, PathBuf, Config) {
+        let config_dir =
+            env::temp_dir().join(format!("toip-watch-{}-{}", name, std::process::id()));
+        for dir in ["api", "web", "src"] {
+            fs::create_dir_all(config_dir.join(dir)).unwrap();
+        }
+        let config_path = config_dir.join("toip.yaml");
+        fs::write(&config_path, WATCHED_CONFIG).unwrap();
+        let config = Config::new(WATCHED_CONFIG.as_bytes()).unwrap();
+
+        (config_path, config_dir, config)
+    }
+
+    #[test]
+    fn watches_existing_sources() {
+        let (config_path, config_dir, config) = watched_project("paths");
+        let mut paths = watch_paths(&config_path, &config_dir, &config);
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        paths.sort();
+        let mut expected = vec![
+            config_path,
+            config_dir.join("api"),
+            config_dir.join("web"),
+            config_dir.join("src"),
+        ];
+        expected.sort();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn rebuilds_only_changed_build_contexts() {
+        let (_, config_dir, config) = watched_project("builds");
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        let changed = vec![
+            config_dir.join("web/index.html"),
+            config_dir.join("src/main.rs"),
+        ];
+        assert_eq!(changed_builds(&config_dir, &config, &changed), vec!["web"]);
+        assert!(changed_builds(&config_dir, &config, &[config_dir.join("toip.yaml")]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_changed_files() {
+        let (config_path, config_dir, config) = watched_project("change");
+        let paths = watch_paths(&config_path, &config_dir, &config);
+        let source = config_dir.join("src").join("main.rs");
+
+        let writer = {
+            let source = source.clone();
+            tokio::spawn(async move {
+                time::sleep(Duration::from_millis(100)).await;
+                fs::write(source, "fn main() {}").unwrap();
+            })
+        };
+        let changed = time::timeout(Duration::from_secs(10), wait_for_change(&paths))
+            .await
+            .expect("no change reported")
+            .unwrap()
+            .unwrap();
+        writer.await.unwrap();
+        fs::remove_dir_all(&config_dir).unwrap();
+
+        assert!(changed.contains(&source), "{:?}", changed);
+    }
 }
@@ -0,0 +1,53 @@
+use anyhow::{Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::{load_config, serve};
+use crate::config::Platform;
+
+/// Start the first shell which is available in the container
+const DETECT_SHELL: &str = r#"if [ -n "$SHELL" ] && command -v "$SHELL" > /dev/null; then exec "$SHELL"; elif command -v bash > /dev/null; then exec bash; else exec sh; fi"#;
+
+/// Starts a new container with an interactive shell and returns the exit code of the shell
+pub async fn shell(
+    container_name: String,
+    shell: Option<String>,
+    platform: Option<Platform>,
+    allow_privileged: bool,
+    global_config: bool,
+) -> Result<i32> {
+    let (_, config_dir, mut config) = load_config(&platform, allow_privileged, global_config)?;
+
+    let container = config
+        .containers
+        .get_mut(&container_name)
+        .with_context(|| {
+            format!(
+                "container with name `{}` does not exists in configuration",
+                container_name
+            )
+        })?;
+    // Replace the entrypoint, so the shell is started instead of the configured command
+    container.cmd = None;
+    match shell {
+        Some(shell) => {
+            container.entrypoint = Some(shell);
+            container.args = vec![];
+        }
+        None => {
+            container.entrypoint = Some("sh".to_string());
+            container.args = vec!["-c".to_string(), DETECT_SHELL.to_string()];
+        }
+    }
+
+    Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    )
+    .prepare(&container_name, container, &config_dir)
+    .await
+    .with_context(|| format!("could not prepare container `{}`", container_name))?;
+
+    serve(container_name, vec![], config_dir, config).await
+}
@@ -0,0 +1,80 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::load_project_config;
+use crate::config::Config;
+use crate::dirs;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+fn image_display(config: &Config, name: &str, fallback: &str) -> String {
+    config
+        .get_container_by_name(name)
+        .and_then(|container| container.image)
+        .map_or_else(|| fallback.to_string(), |image| image.to_string())
+}
+
+async fn print_status(config: &Config, config_dir: &Path) -> Result<()> {
+    let socket = dirs::socket_path().context("could not determine socket path")?;
+    let backend = Backend::new(
+        "docker",
+        &socket,
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+
+    if !socket.exists() {
+        log::info!("no call socket found at `{}`", socket.display());
+    }
+
+    let mut names: Vec<_> = config.containers.keys().collect();
+    names.sort();
+
+    println!("{:<20} {:<40} {:<25} PORTS", "CONTAINER", "IMAGE", "STATUS");
+    for name in names {
+        let running = backend
+            .running(name, config_dir, false)
+            .await
+            .with_context(|| format!("could not determine status of container `{}`", name))?;
+
+        if running.is_empty() {
+            println!(
+                "{:<20} {:<40} {:<25}",
+                name,
+                image_display(config, name, "-"),
+                "Not running"
+            );
+        }
+
+        for instance in running {
+            println!(
+                "{:<20} {:<40} {:<25} {}",
+                name,
+                image_display(config, name, &instance.image),
+                instance.status,
+                instance.ports
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn status(watch: bool, global_config: bool) -> Result<()> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    if !watch {
+        return print_status(&config, &config_dir).await;
+    }
+
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        // Clear the terminal and move the cursor to the top left corner
+        print!("\x1B[2J\x1B[H");
+        print_status(&config, &config_dir).await?;
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::command::run::load_project_config;
+use crate::dirs;
+
+/// Names of the containers which were started detached from the configuration directory
+fn detached_containers(config_dir: &Path) -> Result<Vec<String>> {
+    let running_dir = dirs::running_dir(config_dir)?;
+    if !running_dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut names = vec![];
+    for entry in fs::read_dir(&running_dir)
+        .with_context(|| format!("could not read directory `{}`", running_dir.display()))?
+    {
+        let entry = entry?;
+        names.push(entry.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+pub async fn stop(
+    container: Option<String>,
+    all: bool,
+    timeout: Option<u32>,
+    global_config: bool,
+) -> Result<()> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+
+    let containers = match (container, all) {
+        (Some(container), _) => vec![container],
+        (None, true) => detached_containers(&config_dir)?,
+        (None, false) => bail!("either a container or `--all` is required"),
+    };
+    if containers.is_empty() {
+        log::info!("no detached containers to stop");
+    }
+
+    let timeout = timeout.map(|timeout| Duration::from_secs(timeout.into()));
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+    for container_name in containers {
+        config
+            .get_container_by_name(&container_name)
+            .with_context(|| {
+                format!(
+                    "container with name `{}` does not exists in configuration",
+                    container_name
+                )
+            })?;
+
+        backend.stop(&container_name, &config_dir, timeout).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::{Captures, Regex};
+use which::which;
+
+use crate::config::{Config, CONFIG_FILE_NAME};
+
+const REGISTRY_VARIABLE: &str = "TOIP_TEMPLATE_REGISTRY";
+
+fn template_url(registry: &str, name: &str) -> String {
+    let registry = registry
+        .trim_start_matches("https://")
+        .trim_end_matches('/');
+    format!(
+        "https://{}/templates/{}/{}",
+        registry, name, CONFIG_FILE_NAME
+    )
+}
+
+/// Download the file, there is no HTTP client in toip so curl is used like docker is
+pub fn download(url: &str) -> Result<String> {
+    let curl = which("curl").context("could not find `curl` in $PATH")?;
+    let mut command = Command::new(curl);
+    command.args([
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--location",
+        "--proto",
+        "=https",
+        url,
+    ]);
+    log::trace!("{:#?}", command);
+
+    let output = command
+        .output()
+        .with_context(|| format!("could not download `{}`", url))?;
+    if !output.status.success() {
+        bail!(
+            "could not download `{}`: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    String::from_utf8(output.stdout).with_context(|| format!("`{}` is not UTF-8", url))
+}
+
+/// Replace the `{{VAR}}` placeholders, values passed with `--set` take precedence over the
+/// environment variables which are explicitly inherited
+fn substitute(
+    template: &str,
+    values: &HashMap<String, String>,
+    inherit: &[String],
+) -> Result<String> {
+    let placeholder = Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").unwrap();
+
+    let mut missing = vec![];
+    let content = placeholder.replace_all(template, |captures: &Captures| {
+        let name = &captures[1];
+        let inherited = || {
+            inherit
+                .iter()
+                .find(|inherited| *inherited == name)
+                .and_then(|name| env::var(name).ok())
+        };
+        match values.get(name).cloned().or_else(inherited) {
+            Some(value) => value,
+            None => {
+                missing.push(name.to_string());
+                String::new()
+            }
+        }
+    });
+
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        bail!(
+            "no value for template variable(s) `{}`, use `--set KEY=VALUE` or `--env KEY` to provide them",
+            missing.join("`, `")
+        );
+    }
+
+    Ok(content.into_owned())
+}
+
+pub fn parse_key_value(value: &str) -> Result<(String, String)> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected `KEY=VALUE`, got `{}`", value))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Download the template from the registry and fill in its variables
+fn render<F>(
+    name: &str,
+    registry: &str,
+    values: &HashMap<String, String>,
+    inherit: &[String],
+    download: F,
+) -> Result<String>
+where
+    F: FnOnce(&str) -> Result<String>,
+{
+    let url = template_url(registry, name);
+    log::info!("downloading template `{}` from `{}`", name, url);
+    let template = download(&url)?;
+
+    let content = substitute(&template, values, inherit)
+        .with_context(|| format!("could not render template `{}`", name))?;
+    Config::new(content.as_bytes())
+        .with_context(|| format!("template `{}` is not a valid configuration", name))?;
+
+    Ok(content)
+}
+
+pub fn template(
+    name: String,
+    output: Option<PathBuf>,
+    registry: Option<String>,
+    values: Vec<(String, String)>,
+    inherit: Vec<String>,
+    force: bool,
+) -> Result<()> {
+    let registry = registry
+        .or_else(|| env::var(REGISTRY_VARIABLE).ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "no template registry configured, use `--registry` or set `{}`",
+                REGISTRY_VARIABLE
+            )
+        })?;
+    let output = output.unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME));
+    if output.exists() && !force {
+        bail!(
+            "configuration file `{}` already exists, use `--force` to overwrite it",
+            output.display()
+        );
+    }
+
+    let values = values.into_iter().collect();
+    let content = render(&name, &registry, &values, &inherit, download)?;
+
+    fs::write(&output, content)
+        .with_context(|| format!("could not write to file `{}`", output.display()))?;
+    println!("created configuration file `{}`", output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEMPLATE: &str = "containers:\n  node:\n    image: node:{{ VERSION }}\n    env:\n      OWNER: {{TOIP_TEMPLATE_TEST_OWNER}}\n";
+
+    fn values(values: &[(&str, &str)]) -> HashMap<String, String> {
+        values
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Serve the template from the registry, as the download of it would
+    fn registry(url: &str) -> Result<String> {
+        match url {
+            "https://templates.example.com/templates/node/toip.yaml" => Ok(TEMPLATE.to_string()),
+            _ => bail!("could not download `{}`: 404 Not Found", url),
+        }
+    }
+
+    #[test]
+    fn renders_downloaded_template() {
+        let content = render(
+            "node",
+            "https://templates.example.com/",
+            &values(&[("VERSION", "18"), ("TOIP_TEMPLATE_TEST_OWNER", "team")]),
+            &[],
+            registry,
+        )
+        .unwrap();
+
+        assert_eq!(
+            content,
+            "containers:\n  node:\n    image: node:18\n    env:\n      OWNER: team\n"
+        );
+        assert!(render(
+            "missing",
+            "templates.example.com",
+            &values(&[]),
+            &[],
+            registry
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn only_inherited_environment_variables_are_substituted() {
+        env::set_var("TOIP_TEMPLATE_TEST_OWNER", "env");
+        let values = values(&[("VERSION", "18")]);
+
+        let error = substitute(TEMPLATE, &values, &[]).unwrap_err();
+        let inherited = substitute(TEMPLATE, &values, &["TOIP_TEMPLATE_TEST_OWNER".into()]);
+        env::remove_var("TOIP_TEMPLATE_TEST_OWNER");
+
+        assert!(error.to_string().contains("`TOIP_TEMPLATE_TEST_OWNER`"));
+        assert!(inherited.unwrap().contains("OWNER: env"));
+    }
+
+    #[test]
+    fn set_values_take_precedence() {
+        let content = substitute(
+            "{{ VERSION }}",
+            &values(&[("VERSION", "20")]),
+            &["VERSION".into()],
+        )
+        .unwrap();
+
+        assert_eq!(content, "20");
+    }
+}
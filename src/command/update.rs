@@ -0,0 +1,222 @@
+use std::env;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::backend::driver::{Driver, SupportedDriver};
+use crate::backend::Backend;
+use crate::config::{find_config_file, Config, ContainerConfig, Digest, Reference};
+use crate::lock::Lock;
+
+/// Compares the locked digest with the digest the registry serves for the tag, when applying
+/// the latest image is pulled and locked. Returns nothing for images which cannot be updated.
+async fn check<D>(
+    backend: &Backend<D>,
+    lock: &mut Lock,
+    name: &str,
+    container: &ContainerConfig,
+    config_dir: &Path,
+    apply: bool,
+) -> Result<Option<(Option<Digest>, Digest, &'static str)>>
+where
+    D: Driver + Sync,
+{
+    if container.build.is_some() {
+        log::debug!("skipping container `{}` as it is built locally", name);
+        return Ok(None);
+    }
+    let latest = match backend.remote_digest(container).await? {
+        Some(latest) => latest,
+        None => {
+            log::debug!("skipping container `{}` as it is pinned to a digest", name);
+            return Ok(None);
+        }
+    };
+    // Checked by the remote digest lookup
+    let repository = container.image.as_ref().unwrap().repository.clone();
+
+    let current = lock
+        .containers
+        .get(name)
+        .filter(|locked| locked.repository == repository)
+        .map(|locked| locked.digest.clone());
+
+    let status = if current.as_ref() == Some(&latest) {
+        "up-to-date"
+    } else if apply {
+        let mut pinned = container.clone();
+        if let Some(image) = pinned.image.as_mut() {
+            image.reference = Reference::Digest(latest.clone());
+        }
+        backend
+            .pull(name, &pinned, config_dir)
+            .await
+            .with_context(|| format!("could not pull container `{}`", name))?;
+        lock.record(name, &repository, latest.clone());
+        "updated"
+    } else {
+        "update-available"
+    };
+
+    Ok(Some((current, latest, status)))
+}
+
+pub async fn update(container: Option<String>, apply: bool, global_config: bool) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap();
+    let config = Config::new_from_file(&config_path)?.with_global(global_config)?;
+    let mut lock = Lock::load(config_dir)?;
+
+    let mut containers = match container {
+        Some(name) => {
+            let container = config
+                .get_container_by_name(name.as_str())
+                .with_context(|| {
+                    format!(
+                        "container with name `{}` does not exists in configuration",
+                        name
+                    )
+                })?;
+            vec![(name, container)]
+        }
+        None => config.containers.into_iter().collect(),
+    };
+    containers.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+
+    println!(
+        "{:<20} {:<72} {:<72} STATUS",
+        "CONTAINER", "CURRENT", "LATEST"
+    );
+    for (name, container) in containers {
+        let (current, latest, status) =
+            match check(&backend, &mut lock, &name, &container, config_dir, apply).await? {
+                Some(row) => row,
+                None => continue,
+            };
+
+        println!(
+            "{:<20} {:<72} {:<72} {}",
+            name,
+            current.map_or_else(|| "-".to_string(), |digest| digest.to_string()),
+            latest,
+            status
+        );
+    }
+
+    if apply {
+        lock.save(config_dir)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+    use crate::backend::driver::mock::MockDriver;
+    use crate::config::RegistrySource;
+
+    const IMAGE: &str = "ghcr.io/org/tool:1.0";
+
+    fn digest(hex: &str) -> Digest {
+        Digest::try_from(format!("sha256:{}", hex).as_str()).unwrap()
+    }
+
+    fn container() -> ContainerConfig {
+        ContainerConfig {
+            image: Some(RegistrySource::try_from(IMAGE).unwrap()),
+            ..Default::default()
+        }
+    }
+
+    /// The registry serves `bbbb` for the tag
+    fn driver() -> MockDriver {
+        let mut driver = MockDriver::default();
+        driver
+            .remote_digests
+            .insert(IMAGE.to_string(), digest("bbbb"));
+        driver
+    }
+
+    fn backend() -> Backend<MockDriver> {
+        Backend::new("docker", "", driver())
+    }
+
+    #[tokio::test]
+    async fn locked_latest_digest_is_up_to_date() {
+        let mut lock = Lock::default();
+        lock.record("tool", "org/tool", digest("bbbb"));
+
+        let row = check(
+            &backend(),
+            &mut lock,
+            "tool",
+            &container(),
+            Path::new("."),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            row,
+            Some((Some(digest("bbbb")), digest("bbbb"), "up-to-date"))
+        );
+    }
+
+    #[tokio::test]
+    async fn locked_older_digest_has_update() {
+        let mut lock = Lock::default();
+        lock.record("tool", "org/tool", digest("aaaa"));
+
+        let row = check(
+            &backend(),
+            &mut lock,
+            "tool",
+            &container(),
+            Path::new("."),
+            false,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            row,
+            Some((Some(digest("aaaa")), digest("bbbb"), "update-available"))
+        );
+        assert_eq!(lock.containers["tool"].digest, digest("aaaa"));
+    }
+
+    #[tokio::test]
+    async fn apply_pulls_and_locks_latest_digest() {
+        let driver = driver();
+        let pulled = driver.pulled.clone();
+        let backend = Backend::new("docker", "", driver);
+        let mut lock = Lock::default();
+
+        let row = check(
+            &backend,
+            &mut lock,
+            "tool",
+            &container(),
+            Path::new("."),
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(row, Some((None, digest("bbbb"), "updated")));
+        assert_eq!(lock.containers["tool"].digest, digest("bbbb"));
+        assert_eq!(
+            *pulled.lock().unwrap(),
+            vec![format!("ghcr.io/org/tool@{}", digest("bbbb"))]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::config::{find_config_file, Config, ContainerConfig};
+
+fn resolve<P>(config_dir: &Path, path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        config_dir.join(path)
+    }
+}
+
+fn validate_container(
+    config: &Config,
+    config_dir: &Path,
+    name: &str,
+    container: &ContainerConfig,
+) -> Vec<String> {
+    let mut errors = vec![];
+
+    for (destination, volume_name) in &container.volumes {
+        if !config.volumes.contains_key(volume_name) {
+            errors.push(format!(
+                "container `{}`: volume `{}` mounted at `{}` is not defined in `volumes`",
+                name,
+                volume_name,
+                destination.display()
+            ));
+        }
+    }
+
+    for (link, target) in &container.links {
+        if !config.containers.contains_key(target) {
+            errors.push(format!(
+                "container `{}`: link `{}` points to undefined container `{}`",
+                name, link, target
+            ));
+        }
+    }
+
+    if let Some(build) = &container.build {
+        let context = resolve(config_dir, &build.context);
+        if !context.is_dir() {
+            errors.push(format!(
+                "container `{}`: build context `{}` does not exist",
+                name,
+                context.display()
+            ));
+        }
+
+        if let Some(file) = &build.file {
+            let file = resolve(config_dir, file);
+            if !file.is_file() {
+                errors.push(format!(
+                    "container `{}`: build file `{}` does not exist",
+                    name,
+                    file.display()
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+/// All errors of the configuration, ordered by container name
+pub fn validate_config(config: &Config, config_dir: &Path) -> Vec<String> {
+    let mut containers: Vec<_> = config.containers.iter().collect();
+    containers.sort_by_key(|(name, _)| *name);
+
+    containers
+        .into_iter()
+        .flat_map(|(name, container)| validate_container(config, config_dir, name, container))
+        .collect()
+}
+
+pub fn validate(strict: bool) -> Result<()> {
+    let current_dir = env::current_dir()?;
+    let config_path =
+        find_config_file(current_dir).ok_or_else(|| anyhow!("Unable to find config file"))?;
+    let config_dir = config_path.parent().unwrap().to_path_buf();
+    let config = Config::new_from_file(&config_path)?;
+
+    if strict {
+        let mut names: Vec<_> = config.containers.keys().collect();
+        names.sort();
+        for name in names {
+            let container = &config.containers[name];
+            if container.image.is_none() && container.build.is_none() {
+                eprintln!(
+                    "{}: warning: container `{}` has no `image` or `build` configured",
+                    config_path.display(),
+                    name
+                );
+            }
+        }
+    }
+
+    let errors = validate_config(&config, &config_dir);
+
+    for error in &errors {
+        eprintln!("{}: error: {}", config_path.display(), error);
+    }
+
+    if !errors.is_empty() {
+        bail!(
+            "configuration file `{}` contains {} error(s)",
+            config_path.display(),
+            errors.len()
+        );
+    }
+
+    println!("configuration file `{}` is valid", config_path.display());
+    Ok(())
+}
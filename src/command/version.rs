@@ -0,0 +1,132 @@
+use std::cmp::Ordering;
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use serde_derive::Deserialize;
+
+use crate::command::template::download;
+use crate::dirs;
+use crate::metadata::{APPLICATION_NAME, VERSION};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/waaghals/toip/releases/latest";
+const CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Numeric components of the version and its pre-release, ignoring the `v` prefix and the build
+/// metadata, components without leading digits count as zero
+fn components(version: &str) -> (Vec<u64>, Option<&str>) {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split('+').next().unwrap_or_default();
+    let (version, pre_release) = match version.split_once('-') {
+        Some((version, pre_release)) => (version, Some(pre_release)),
+        None => (version, None),
+    };
+    let numbers = version
+        .split('.')
+        .map(|component| {
+            let digits = component.len()
+                - component
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            component[..digits].parse().unwrap_or(0)
+        })
+        .collect();
+
+    (numbers, pre_release)
+}
+
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let (mut latest, latest_pre_release) = components(latest);
+    let (mut current, current_pre_release) = components(current);
+    // Missing components are zero, so `1.0` equals `1.0.0`
+    let length = latest.len().max(current.len());
+    latest.resize(length, 0);
+    current.resize(length, 0);
+
+    match latest.cmp(&current) {
+        Ordering::Greater => true,
+        Ordering::Less => false,
+        // A release is newer than its pre-releases
+        Ordering::Equal => latest_pre_release.is_none() && current_pre_release.is_some(),
+    }
+}
+
+fn checked_recently() -> Result<bool> {
+    let marker = dirs::last_update_check()?;
+    let modified = match fs::metadata(&marker).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|elapsed| elapsed < CHECK_INTERVAL))
+}
+
+fn record_check() -> Result<()> {
+    let marker = dirs::last_update_check()?;
+    if let Some(dir) = marker.parent() {
+        dirs::create(dir)?;
+    }
+    fs::write(&marker, "")
+        .with_context(|| format!("could not write to file `{}`", marker.display()))
+}
+
+pub fn version(check: bool) -> Result<()> {
+    println!("{} {}", APPLICATION_NAME, VERSION);
+    if !check {
+        return Ok(());
+    }
+
+    if checked_recently()? {
+        log::info!("already checked for a newer version in the last day");
+        return Ok(());
+    }
+
+    let response = download(RELEASES_URL).context("could not check for a newer version")?;
+    let release: Release =
+        serde_json::from_str(&response).context("could not parse the latest release")?;
+    record_check()?;
+
+    if is_newer(&release.tag_name, VERSION) {
+        println!(
+            "version {} is available, download it from {}",
+            release.tag_name.trim_start_matches('v'),
+            release.html_url
+        );
+    } else {
+        println!("{} is up to date", APPLICATION_NAME);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_newer("v1.2.0", "1.1.9"));
+        assert!(is_newer("1.10.0", "1.9.0"));
+        assert!(is_newer("1.0.1", "1.0"));
+        assert!(!is_newer("1.0", "1.0.0"));
+        assert!(!is_newer("1.0.0", "1.0"));
+        assert!(!is_newer("1.1.0", "1.2.0"));
+    }
+
+    #[test]
+    fn compares_non_numeric_versions() {
+        assert!(is_newer("1.0.0", "1.0.0-rc.1"));
+        assert!(!is_newer("1.0.0-rc.1", "1.0.0"));
+        assert!(!is_newer("1.0.0+build.5", "1.0.0"));
+        assert!(is_newer("1.3rc", "1.2"));
+        assert!(!is_newer("1.x", "1.0"));
+    }
+}
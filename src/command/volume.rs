@@ -0,0 +1,211 @@
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tar::{Archive, Builder};
+
+use crate::backend::driver::SupportedDriver;
+use crate::backend::Backend;
+use crate::cli::VolumeCommand;
+use crate::command::run::load_project_config;
+use crate::config::{Config, Volume};
+use crate::dirs;
+
+/// Lock file next to the volume directory, removed again when dropped
+struct VolumeLock {
+    path: PathBuf,
+}
+
+impl VolumeLock {
+    fn acquire(directory: &Path) -> Result<VolumeLock> {
+        let mut path = OsString::from(directory.as_os_str());
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_context(|| {
+                format!(
+                    "could not lock volume `{}`, remove `{}` if no other process uses it",
+                    directory.display(),
+                    path.display()
+                )
+            })?;
+
+        Ok(VolumeLock { path })
+    }
+}
+
+impl Drop for VolumeLock {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_file(&self.path) {
+            log::warn!("could not remove lock `{}`: {}", self.path.display(), error);
+        }
+    }
+}
+
+fn volume_dir(name: &str, config: &Config, config_dir: &Path) -> Result<PathBuf> {
+    let volume = config
+        .volumes
+        .get(name)
+        .with_context(|| format!("volume `{}` does not exists in configuration", name))?;
+
+    match volume {
+        Volume::Anonymous(anonymous) => {
+            let seed = if anonymous.external {
+                None
+            } else {
+                Some(config_dir)
+            };
+            dirs::volume(anonymous.name.clone(), seed)
+        }
+        _ => bail!("volume `{}` is not a named volume", name),
+    }
+}
+
+/// The volume lock is advisory, running containers keep writing to the volume regardless
+async fn ensure_unused(name: &str, config: &Config, config_dir: &Path) -> Result<()> {
+    let backend = Backend::new(
+        "docker",
+        "",
+        SupportedDriver::resolve_with_supported_binary()?,
+    );
+
+    let mut users: Vec<_> = config
+        .containers
+        .iter()
+        .filter(|(_, container)| container.volumes.values().any(|volume| volume == name))
+        .map(|(container_name, _)| container_name)
+        .collect();
+    users.sort();
+    for container_name in users {
+        let running = backend
+            .running(container_name, config_dir, false)
+            .await
+            .with_context(|| {
+                format!(
+                    "could not determine status of container `{}`",
+                    container_name
+                )
+            })?;
+        if !running.is_empty() {
+            bail!(
+                "volume `{}` is used by running container `{}`, stop it first",
+                name,
+                container_name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn backup(directory: &Path, output: &Path) -> Result<()> {
+    if !directory.is_dir() {
+        bail!("volume directory `{}` does not exist", directory.display());
+    }
+
+    let file = File::create(output)
+        .with_context(|| format!("could not create archive `{}`", output.display()))?;
+    let mut archive = Builder::new(GzEncoder::new(file, Compression::default()));
+    archive
+        .append_dir_all(".", directory)
+        .with_context(|| format!("could not archive `{}`", directory.display()))?;
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("could not write archive `{}`", output.display()))?;
+
+    println!(
+        "backed up `{}` to `{}`",
+        directory.display(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn restore(directory: &Path, input: &Path) -> Result<()> {
+    let file = File::open(input)
+        .with_context(|| format!("could not open archive `{}`", input.display()))?;
+
+    // Extract next to the volume first, so a broken archive leaves the volume intact
+    let mut staging = OsString::from(directory.as_os_str());
+    staging.push(".restore");
+    let staging = PathBuf::from(staging);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .with_context(|| format!("could not remove `{}`", staging.display()))?;
+    }
+
+    Archive::new(GzDecoder::new(file))
+        .unpack(&staging)
+        .with_context(|| format!("could not extract archive `{}`", input.display()))?;
+
+    // Swap the volumes by renaming, so the volume is never lost halfway
+    let mut previous = OsString::from(directory.as_os_str());
+    previous.push(".previous");
+    let previous = PathBuf::from(previous);
+    if previous.exists() {
+        fs::remove_dir_all(&previous)
+            .with_context(|| format!("could not remove `{}`", previous.display()))?;
+    }
+    let existed = directory.exists();
+    if existed {
+        fs::rename(directory, &previous)
+            .with_context(|| format!("could not move volume `{}` aside", directory.display()))?;
+    }
+    if let Err(error) = fs::rename(&staging, directory) {
+        if existed {
+            fs::rename(&previous, directory).with_context(|| {
+                format!(
+                    "could not move volume back from `{}`, restore it manually",
+                    previous.display()
+                )
+            })?;
+        }
+        return Err(error)
+            .with_context(|| format!("could not replace volume `{}`", directory.display()));
+    }
+    if existed {
+        fs::remove_dir_all(&previous)
+            .with_context(|| format!("could not remove `{}`", previous.display()))?;
+    }
+
+    println!(
+        "restored `{}` from `{}`",
+        directory.display(),
+        input.display()
+    );
+
+    Ok(())
+}
+
+pub async fn volume(command: VolumeCommand, global_config: bool) -> Result<()> {
+    let (_, config_dir, config) = load_project_config(global_config)?;
+    match command {
+        VolumeCommand::Backup { name, output } => {
+            let directory = volume_dir(&name, &config, &config_dir)?;
+            ensure_unused(&name, &config, &config_dir).await?;
+            let _lock = VolumeLock::acquire(&directory)?;
+            backup(&directory, &output)
+        }
+        VolumeCommand::Restore { name, input } => {
+            let directory = volume_dir(&name, &config, &config_dir)?;
+            ensure_unused(&name, &config, &config_dir).await?;
+            if let Some(parent) = directory.parent() {
+                fs::create_dir_all(parent).with_context(|| {
+                    format!("could not create directory `{}`", parent.display())
+                })?;
+            }
+            let _lock = VolumeLock::acquire(&directory)?;
+            restore(&directory, &input)
+        }
+    }
+}
@@ -5,27 +5,64 @@ use std::fs::File;
 use std::io::{BufReader, Read};
 use std::marker::PhantomData;
 use std::num::ParseIntError;
+use std::ops::RangeInclusive;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::{fmt, str};
+use std::time::Duration;
+use std::{env, fmt, fs, str};
 
 use anyhow::{anyhow, bail, Context, Result};
 use regex::Regex;
 use serde::de::{Error, MapAccess, Unexpected, Visitor};
+use serde::ser::SerializeStruct;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use serde_derive::{Deserialize as DeriveDeserialize, Serialize as DeriveSerialize};
 use sha2::{Digest as Sha2Digest, Sha256};
 
-const CONFIG_FILE_NAME: &str = "toip.yaml";
+use crate::backend::{BindConsistency, BindPropagation};
+use crate::dirs;
 
-#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
+pub const CONFIG_FILE_NAME: &str = "toip.yaml";
+pub const JSON_CONFIG_FILE_NAME: &str = "toip.json";
+const CONFIG_FILE_NAMES: [&str; 2] = [CONFIG_FILE_NAME, JSON_CONFIG_FILE_NAME];
+pub const PROFILE_VARIABLE: &str = "TOIP_PROFILE";
+pub const CONFIG_VARIABLE: &str = "TOIP_CONFIG";
+pub const INSECURE_REGISTRIES_VARIABLE: &str = "TOIP_INSECURE_REGISTRIES";
+
+const SIGNALS: [&str; 19] = [
+    "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2",
+    "PIPE", "ALRM", "TERM", "CHLD", "CONT", "STOP", "TSTP",
+];
+
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize)]
 pub struct RegistrySource {
     #[serde(default)]
     pub registry: String,
     pub repository: String,
     #[serde(default)]
     pub reference: Reference,
+    /// Pull over plain HTTP, for local development registries
+    #[serde(default)]
+    pub insecure: Option<bool>,
+}
+
+impl RegistrySource {
+    /// Host of the registry, without the namespace
+    pub fn host(&self) -> &str {
+        self.registry.split('/').next().unwrap_or_default()
+    }
+
+    /// Explicitly marked insecure, or listed in `TOIP_INSECURE_REGISTRIES`
+    pub fn is_insecure(&self) -> bool {
+        self.insecure.unwrap_or_else(|| {
+            env::var(INSECURE_REGISTRIES_VARIABLE).is_ok_and(|registries| {
+                registries
+                    .split(',')
+                    .any(|registry| registry.trim() == self.host())
+            })
+        })
+    }
 }
 
 impl Default for RegistrySource {
@@ -35,6 +72,7 @@ impl Default for RegistrySource {
             // TODO hash based on container config
             repository: "123456789".to_string(),
             reference: Default::default(),
+            insecure: None,
         }
     }
 }
@@ -50,7 +88,36 @@ impl fmt::Display for RegistrySource {
     }
 }
 
-const REGISTRY_PATTERN: &str = r"^(?:(?P<registry>(?:[a-zA-Z0-9]+\.[a-zA-Z0-9.]+?)|[a-zA-Z0-9]+\.)/)?(?P<repository>[a-z0-9][a-z0-9._-]*(?:/[a-z0-9][a-z0-9._-]*)?)(?:(?::(?P<tag>[a-zA-Z0-9_][a-zA-Z0-9._-]*))|@(?P<digest>[a-zA-Z0-9]+:[a-zA-Z0-9]+))?$";
+impl Serialize for RegistrySource {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // The string form cannot hold the insecure flag
+        if let Some(insecure) = self.insecure {
+            let mut state = serializer.serialize_struct("RegistrySource", 4)?;
+            state.serialize_field("registry", &self.registry)?;
+            state.serialize_field("repository", &self.repository)?;
+            state.serialize_field("reference", &self.reference)?;
+            state.serialize_field("insecure", &insecure)?;
+            return state.end();
+        }
+
+        // Omit the implicit registry so the value parses back to the same source
+        if self.registry == DEFAULT_REGISTRY {
+            let val = match &self.reference {
+                Reference::Digest(digest) => format!("{}@{}", self.repository, digest),
+                Reference::Tag(tag) => format!("{}:{}", self.repository, tag),
+            };
+            serializer.serialize_str(val.as_str())
+        } else {
+            serializer.collect_str(self)
+        }
+    }
+}
+
+const DEFAULT_REGISTRY: &str = "docker.io/library";
+const REGISTRY_PATTERN: &str = r"^(?:(?P<registry>(?:[a-zA-Z0-9]+\.[a-zA-Z0-9.]+?)|[a-zA-Z0-9]+\.)/)?(?P<repository>[a-z0-9][a-z0-9._-]*(?:/[a-z0-9][a-z0-9._-]*)?)(?:(?::(?P<tag>[a-zA-Z0-9_][a-zA-Z0-9._-]*))|@(?P<digest>[a-z0-9]+(?:[+._-][a-z0-9]+)?:[a-zA-Z0-9=_-]+))?$";
 impl TryFrom<&str> for RegistrySource {
     type Error = anyhow::Error;
 
@@ -62,7 +129,7 @@ impl TryFrom<&str> for RegistrySource {
 
         let registry = match captures.name("registry") {
             Some(registry_match) => registry_match.as_str(),
-            None => "docker.io/library",
+            None => DEFAULT_REGISTRY,
         };
         let reference = match captures.name("digest") {
             Some(digest_match) => {
@@ -83,6 +150,7 @@ impl TryFrom<&str> for RegistrySource {
             registry: registry.into(),
             repository: repository.into(),
             reference,
+            insecure: None,
         })
     }
 }
@@ -128,6 +196,8 @@ impl TryFrom<&str> for Digest {
         let algorithm = match captured_algorithm {
             "sha256" => Ok(Algorithm::SHA256),
             "sha512" => Ok(Algorithm::SHA512),
+            "sha3-256" => Ok(Algorithm::SHA3_256),
+            "blake3" => Ok(Algorithm::BLAKE3),
             _ => Err(anyhow!(
                 "unsupported algorithm `{}` in digest `{}`",
                 captured_algorithm,
@@ -175,6 +245,8 @@ impl fmt::Display for Reference {
 pub enum Algorithm {
     SHA256,
     SHA512,
+    SHA3_256,
+    BLAKE3,
 }
 
 impl fmt::Display for Algorithm {
@@ -182,6 +254,8 @@ impl fmt::Display for Algorithm {
         match self {
             Algorithm::SHA256 => write!(f, "sha256"),
             Algorithm::SHA512 => write!(f, "sha512"),
+            Algorithm::SHA3_256 => write!(f, "sha3-256"),
+            Algorithm::BLAKE3 => write!(f, "blake3"),
         }
     }
 }
@@ -223,27 +297,98 @@ impl FromStr for BuildSource {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, DeriveDeserialize)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.variant {
+            Some(variant) => write!(f, "{}/{}/{}", self.os, self.architecture, variant),
+            None => write!(f, "{}/{}", self.os, self.architecture),
+        }
+    }
+}
+
+const PLATFORM_PATTERN: &str =
+    "^(?P<os>[a-z0-9_]+)/(?P<architecture>[a-z0-9_]+)(?:/(?P<variant>[a-z0-9_]+))?$";
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let regex = Regex::new(PLATFORM_PATTERN).unwrap();
+        let captures = regex
+            .captures(value)
+            .with_context(|| format!("platform `{}` could not be parsed", value))?;
+
+        Ok(Platform {
+            os: captures.name("os").unwrap().as_str().to_string(),
+            architecture: captures.name("architecture").unwrap().as_str().to_string(),
+            variant: captures
+                .name("variant")
+                .map(|variant| variant.as_str().to_string()),
+        })
+    }
+}
+
+impl Serialize for Platform {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Platform {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let string = EnvString::deserialize(deserializer)?.into_inner();
+        Platform::from_str(string.as_str()).map_err(de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
 pub struct BindVolume {
     pub source: EnvPathBuf,
     #[serde(default)]
     pub readonly: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consistency: Option<BindConsistency>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub propagation: Option<BindPropagation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub non_recursive: Option<bool>,
 }
 
-#[derive(Debug, Clone, PartialEq, DeriveDeserialize)]
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
 pub struct AnonymousVolume {
     pub name: EnvString,
     #[serde(default)]
     pub external: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, DeriveDeserialize)]
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
+pub struct TmpfsVolume {
+    pub size: Option<String>,
+    /// Permission bits of the mount, written as octal in YAML, i.e. `0o1777`
+    pub mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
 #[serde(tag = "type")]
 pub enum Volume {
     #[serde(rename = "volume")]
     Anonymous(AnonymousVolume),
     #[serde(rename = "bind")]
     Bind(BindVolume),
+    #[serde(rename = "tmpfs")]
+    Tmpfs(TmpfsVolume),
 }
 #[derive(Debug, Clone, PartialEq, DeriveSerialize)]
 pub struct Port {
@@ -251,7 +396,31 @@ pub struct Port {
     pub container: u16,
 }
 
-#[derive(Debug, DeriveDeserialize, DeriveSerialize, Clone)]
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
+pub struct HealthCheck {
+    pub test: Vec<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "duration")]
+    pub interval: Option<Duration>,
+    #[serde(default)]
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    pub retries: Option<u32>,
+    #[serde(default)]
+    #[serde(deserialize_with = "duration")]
+    pub start_period: Option<Duration>,
+}
+
+/// When the image of a container is pulled, builds are not affected
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+#[derive(Debug, Default, DeriveDeserialize, DeriveSerialize, Clone)]
 pub struct ContainerConfig {
     #[serde(default)]
     #[serde(deserialize_with = "registry")]
@@ -266,6 +435,8 @@ pub struct ContainerConfig {
     pub cmd: Option<String>,
     #[serde(default)]
     pub args: Vec<String>,
+    /// File with an argument on each line, appended to `args` when loading the configuration
+    pub args_file: Option<EnvPathBuf>,
     #[serde(default)]
     pub volumes: HashMap<PathBuf, String>,
     #[serde(default)]
@@ -273,14 +444,169 @@ pub struct ContainerConfig {
     #[serde(default)]
     pub inherit_envvars: Vec<String>,
     #[serde(default)]
+    pub inherit_all_envvars: bool,
+    #[serde(default)]
+    pub env_files: Vec<EnvPathBuf>,
+    #[serde(default, deserialize_with = "ports")]
     pub ports: Vec<Port>,
+    pub healthcheck: Option<HealthCheck>,
+    pub platform: Option<Platform>,
+    #[serde(default)]
+    #[serde(deserialize_with = "capabilities")]
+    pub cap_add: Vec<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "capabilities")]
+    pub cap_drop: Vec<String>,
+    pub read_only: Option<bool>,
+    pub privileged: Option<bool>,
+    pub init: Option<bool>,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "annotations")]
+    pub annotations: HashMap<String, String>,
+    pub user: Option<String>,
+    pub seccomp_profile: Option<EnvPathBuf>,
+    #[serde(default)]
+    #[serde(deserialize_with = "extra_hosts")]
+    pub extra_hosts: Vec<String>,
+    #[serde(default)]
+    pub networks: Vec<String>,
+    pub inherit_workdir: Option<bool>,
+    pub auto_mount_cwd: Option<PathBuf>,
+    #[serde(default)]
+    #[serde(deserialize_with = "duration")]
+    pub timeout: Option<Duration>,
+    pub pull_policy: Option<PullPolicy>,
+    #[serde(default)]
+    #[serde(deserialize_with = "signal")]
+    pub stop_signal: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "duration")]
+    pub stop_grace_period: Option<Duration>,
+    pub extends: Option<String>,
+}
+
+impl ContainerConfig {
+    /// Hash of the configuration and the build file the image is built from,
+    /// so a changed build gets a new image
+    pub fn content_hash(&self, build_file: Option<&[u8]>) -> Result<String> {
+        // Values sort their keys, which makes the hash independent of the order of the hash maps
+        let value = serde_json::to_value((&self.build, &self.platform))
+            .context("could not serialize container configuration")?;
+        let data =
+            serde_json::to_vec(&value).context("could not serialize container configuration")?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(&data);
+        if let Some(build_file) = build_file {
+            hasher.update(build_file);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    /// Fill the unset fields of this container with the values of `base`
+    fn inherit(mut self, base: &ContainerConfig) -> ContainerConfig {
+        fn fallback<T: Clone>(value: &mut Option<T>, base: &Option<T>) {
+            if value.is_none() {
+                *value = base.clone();
+            }
+        }
+
+        fn fallback_empty<T: Clone>(value: &mut T, base: &T)
+        where
+            for<'a> &'a T: IntoIterator,
+        {
+            if (&*value).into_iter().next().is_none() {
+                *value = base.clone();
+            }
+        }
+
+        fallback(&mut self.image, &base.image);
+        fallback(&mut self.build, &base.build);
+        fallback_empty(&mut self.links, &base.links);
+        fallback(&mut self.entrypoint, &base.entrypoint);
+        fallback(&mut self.workdir, &base.workdir);
+        fallback(&mut self.cmd, &base.cmd);
+        fallback_empty(&mut self.args, &base.args);
+        fallback(&mut self.args_file, &base.args_file);
+        fallback_empty(&mut self.volumes, &base.volumes);
+        fallback_empty(&mut self.env, &base.env);
+        fallback_empty(&mut self.inherit_envvars, &base.inherit_envvars);
+        self.inherit_all_envvars |= base.inherit_all_envvars;
+        fallback_empty(&mut self.env_files, &base.env_files);
+        fallback_empty(&mut self.ports, &base.ports);
+        fallback(&mut self.healthcheck, &base.healthcheck);
+        fallback(&mut self.platform, &base.platform);
+        fallback_empty(&mut self.cap_add, &base.cap_add);
+        fallback_empty(&mut self.cap_drop, &base.cap_drop);
+        fallback(&mut self.read_only, &base.read_only);
+        fallback(&mut self.privileged, &base.privileged);
+        fallback(&mut self.init, &base.init);
+        fallback_empty(&mut self.labels, &base.labels);
+        fallback_empty(&mut self.annotations, &base.annotations);
+        fallback(&mut self.user, &base.user);
+        fallback(&mut self.seccomp_profile, &base.seccomp_profile);
+        fallback_empty(&mut self.extra_hosts, &base.extra_hosts);
+        fallback_empty(&mut self.networks, &base.networks);
+        fallback(&mut self.inherit_workdir, &base.inherit_workdir);
+        fallback(&mut self.auto_mount_cwd, &base.auto_mount_cwd);
+        fallback(&mut self.timeout, &base.timeout);
+        fallback(&mut self.pull_policy, &base.pull_policy);
+        fallback(&mut self.stop_signal, &base.stop_signal);
+        fallback(&mut self.stop_grace_period, &base.stop_grace_period);
+
+        self
+    }
+}
+
+fn default_network_driver() -> String {
+    "bridge".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, DeriveDeserialize, DeriveSerialize)]
+pub struct NetworkDef {
+    #[serde(default = "default_network_driver")]
+    pub driver: String,
+    #[serde(default)]
+    pub internal: bool,
 }
 
-#[derive(Debug, DeriveDeserialize, Clone)]
+/// Fallback values which are applied to every container
+#[derive(Debug, Default, DeriveDeserialize, DeriveSerialize, Clone)]
+pub struct ContainerDefaults {
+    #[serde(default)]
+    pub env: HashMap<String, EnvString>,
+    #[serde(default)]
+    pub volumes: HashMap<PathBuf, String>,
+    pub workdir: Option<PathBuf>,
+    #[serde(default)]
+    pub inherit_envvars: Vec<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "capabilities")]
+    pub cap_drop: Vec<String>,
+    pub read_only: Option<bool>,
+}
+
+#[derive(Debug, DeriveDeserialize, DeriveSerialize, Clone)]
 pub struct Config {
     pub containers: HashMap<String, ContainerConfig>,
     #[serde(default)]
     pub volumes: HashMap<String, Volume>,
+    #[serde(default)]
+    pub networks: HashMap<String, NetworkDef>,
+    #[serde(default)]
+    pub profiles: HashMap<String, PartialConfig>,
+    pub defaults: Option<ContainerDefaults>,
+}
+
+/// Overrides of the configuration which are applied when the profile is selected
+#[derive(Debug, Default, DeriveDeserialize, DeriveSerialize, Clone)]
+pub struct PartialConfig {
+    #[serde(default)]
+    pub containers: HashMap<String, ContainerConfig>,
+    #[serde(default)]
+    pub volumes: HashMap<String, Volume>,
 }
 
 #[derive(Debug, DeriveDeserialize)]
@@ -305,25 +631,287 @@ impl Config {
             .read_to_string(&mut contents)
             .context("unable to read config")?;
 
-        serde_yaml::from_str(&contents).context("unable to parse config")
+        let config: Config = if contents.trim_start().starts_with('{') {
+            // Report the JSON error, YAML flow mappings also start with a brace
+            serde_json::from_str(&contents)
+                .or_else(|error| serde_yaml::from_str(&contents).map_err(|_| error))
+                .context("unable to parse config")?
+        } else {
+            match serde_yaml::from_str(&contents) {
+                Ok(config) => config,
+                // Report the YAML error, as that is the expected format
+                Err(error) => serde_json::from_str(&contents)
+                    .map_err(|_| error)
+                    .context("unable to parse config")?,
+            }
+        };
+
+        config.finish()
     }
 
-    pub fn new_from_dir<D>(dir: D) -> Result<Config>
+    pub fn from_json<R>(read: R) -> Result<Config>
     where
-        D: Into<PathBuf>,
+        R: Read,
     {
-        let mut path = dir.into();
-        path.push(CONFIG_FILE_NAME);
+        let config: Config =
+            serde_json::from_reader(BufReader::new(read)).context("unable to parse config")?;
+
+        config.finish()
+    }
+
+    /// Apply the selected profile, extends and defaults after parsing
+    fn finish(mut self) -> Result<Config> {
+        if let Ok(profile) = env::var(PROFILE_VARIABLE) {
+            self.apply_profile(&profile);
+        }
+        self.resolve_extends()?;
+        self.apply_defaults();
+
+        for name in self.privileged_containers() {
+            log::warn!("container `{}` runs in privileged mode", name);
+        }
+        for (name, volume) in &self.volumes {
+            if let Volume::Bind(BindVolume {
+                propagation: Some(BindPropagation::Slave),
+                ..
+            }) = volume
+            {
+                log::warn!(
+                    "volume `{}` uses the deprecated `slave` propagation, use `rslave` instead",
+                    name
+                );
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Append the arguments of the args files, relative paths are resolved against `base_dir`
+    pub fn resolve_args_files(&mut self, base_dir: &Path) -> Result<()> {
+        for (name, container) in &mut self.containers {
+            let args_file = match container.args_file.take() {
+                Some(args_file) => base_dir.join(args_file),
+                None => continue,
+            };
+            let contents = fs::read_to_string(&args_file).with_context(|| {
+                format!(
+                    "could not read args file `{}` of container `{}`",
+                    args_file.display(),
+                    name
+                )
+            })?;
+            container.args.extend(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn resolve_container(
+        &self,
+        name: &str,
+        resolved: &mut HashMap<String, ContainerConfig>,
+        chain: &mut Vec<String>,
+    ) -> Result<ContainerConfig> {
+        if let Some(container) = resolved.get(name) {
+            return Ok(container.clone());
+        }
+
+        if chain.iter().any(|visited| visited == name) {
+            chain.push(name.to_string());
+            bail!("circular `extends` detected: `{}`", chain.join("` -> `"));
+        }
+
+        let container = self
+            .containers
+            .get(name)
+            .cloned()
+            .with_context(|| format!("extended container `{}` does not exist", name))?;
+
+        let container = match &container.extends {
+            None => container,
+            Some(base_name) => {
+                chain.push(name.to_string());
+                let base = self.resolve_container(base_name, resolved, chain)?;
+                chain.pop();
+                container.inherit(&base)
+            }
+        };
 
+        resolved.insert(name.to_string(), container.clone());
+        Ok(container)
+    }
+
+    /// Override the containers and volumes with the ones of the profile
+    pub fn apply_profile(&mut self, profile: &str) {
+        let partial = match self.profiles.get(profile) {
+            Some(partial) => partial.clone(),
+            None => {
+                log::warn!("profile `{}` does not exist in configuration", profile);
+                return;
+            }
+        };
+
+        log::debug!("applying profile `{}`", profile);
+        for (name, container) in partial.containers {
+            let container = match self.containers.get(&name) {
+                Some(base) => container.inherit(base),
+                None => container,
+            };
+            self.containers.insert(name, container);
+        }
+        self.volumes.extend(partial.volumes);
+    }
+
+    /// Fill in the unset values of all containers from the `defaults` section
+    pub fn apply_defaults(&mut self) {
+        let defaults = match &self.defaults {
+            Some(defaults) => defaults,
+            None => return,
+        };
+
+        for container in self.containers.values_mut() {
+            for (name, value) in &defaults.env {
+                container
+                    .env
+                    .entry(name.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            for (target, source) in &defaults.volumes {
+                container
+                    .volumes
+                    .entry(target.clone())
+                    .or_insert_with(|| source.clone());
+            }
+            if container.workdir.is_none() {
+                container.workdir = defaults.workdir.clone();
+            }
+            if container.inherit_envvars.is_empty() {
+                container.inherit_envvars = defaults.inherit_envvars.clone();
+            }
+            if container.cap_drop.is_empty() {
+                container.cap_drop = defaults.cap_drop.clone();
+            }
+            if container.read_only.is_none() {
+                container.read_only = defaults.read_only;
+            }
+        }
+    }
+
+    /// Expand the `extends` of all containers with the configuration of their base container
+    pub fn resolve_extends(&mut self) -> Result<()> {
+        let mut resolved = HashMap::new();
+        for name in self.containers.keys() {
+            self.resolve_container(name, &mut resolved, &mut vec![])
+                .with_context(|| format!("could not resolve container `{}`", name))?;
+        }
+
+        self.containers = resolved;
+        Ok(())
+    }
+
+    pub fn privileged_containers(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .containers
+            .iter()
+            .filter(|(_, container)| container.privileged == Some(true))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Ensure privileged containers are only used when explicitly allowed
+    pub fn check_privileged(&self, allowed: bool) -> Result<()> {
+        let privileged = self.privileged_containers();
+        if !allowed && !privileged.is_empty() {
+            bail!(
+                "container(s) `{}` require privileged mode, pass `--allow-privileged` to allow it",
+                privileged.join("`, `")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Run all containers on the given platform instead of the configured one
+    pub fn override_platform(&mut self, platform: &Platform) {
+        for container in self.containers.values_mut() {
+            container.platform = Some(platform.clone());
+        }
+    }
+
+    /// Load the user wide configuration, if it exists
+    pub fn new_global() -> Result<Option<Config>> {
+        let path = dirs::global_config().context("could not determine global config path")?;
         if !path.is_file() {
-            bail!("path `{}` is not an file", path.display());
+            return Ok(None);
         }
 
         let file = File::open(&path)
             .with_context(|| format!("could not read configuration file `{}`", path.display()))?;
 
-        Config::new(&file)
-            .with_context(|| format!("could not parse configuration file `{}`", path.display()))
+        let mut config = Config::new(&file)
+            .with_context(|| format!("could not parse configuration file `{}`", path.display()))?;
+        if let Some(dir) = path.parent() {
+            config.resolve_args_files(dir)?;
+        }
+
+        Ok(Some(config))
+    }
+
+    /// Combine both configurations, entries of `self` take precedence over `other`
+    pub fn merge(mut self, other: Config) -> Config {
+        for (name, container) in other.containers {
+            self.containers.entry(name).or_insert(container);
+        }
+        for (name, volume) in other.volumes {
+            self.volumes.entry(name).or_insert(volume);
+        }
+        for (name, network) in other.networks {
+            self.networks.entry(name).or_insert(network);
+        }
+
+        self
+    }
+
+    /// Merge the global configuration into this configuration when enabled
+    pub fn with_global(self, enabled: bool) -> Result<Config> {
+        if !enabled {
+            return Ok(self);
+        }
+
+        match Config::new_global()? {
+            Some(global) => Ok(self.merge(global)),
+            None => Ok(self),
+        }
+    }
+
+    pub fn new_from_file<P>(path: P) -> Result<Config>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("could not read configuration file `{}`", path.display()))?;
+
+        let config = if path.ends_with(JSON_CONFIG_FILE_NAME) {
+            Config::from_json(&file)
+        } else {
+            Config::new(&file)
+        };
+        let mut config = config
+            .with_context(|| format!("could not parse configuration file `{}`", path.display()))?;
+        if let Some(dir) = path.parent() {
+            config.resolve_args_files(dir)?;
+        }
+
+        Ok(config)
     }
 }
 
@@ -331,24 +919,36 @@ pub fn find_config_file<P>(starting_dir: P) -> Option<PathBuf>
 where
     P: Into<PathBuf>,
 {
+    // An explicitly selected configuration skips the directory walk
+    if let Some(config) = env::var_os(CONFIG_VARIABLE) {
+        let config = PathBuf::from(config);
+        if config.is_dir() {
+            return CONFIG_FILE_NAMES
+                .iter()
+                .map(|file_name| config.join(file_name))
+                .find(|path| path.is_file());
+        }
+        return Some(config).filter(|path| path.is_file());
+    }
+
     let mut path: PathBuf = starting_dir.into();
-    let file_name = Path::new(CONFIG_FILE_NAME);
 
     loop {
-        path.push(file_name);
-
-        if path.is_file() {
-            break Some(path);
+        for file_name in CONFIG_FILE_NAMES {
+            let config_path = path.join(file_name);
+            if config_path.is_file() {
+                return Some(config_path);
+            }
         }
 
-        if !(path.pop() && path.pop()) {
-            // remove file && remove parent
-            break None;
+        if !path.pop() {
+            return None;
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, DeriveSerialize)]
+#[serde(transparent)]
 pub struct EnvSub<T> {
     substituted: T,
 }
@@ -359,6 +959,12 @@ impl<T> EnvSub<T> {
     }
 }
 
+impl<T> From<T> for EnvSub<T> {
+    fn from(value: T) -> Self {
+        EnvSub { substituted: value }
+    }
+}
+
 impl<T> AsRef<Path> for EnvSub<T>
 where
     T: AsRef<Path>,
@@ -482,12 +1088,24 @@ where
     }
 }
 
-#[derive(DeriveSerialize, PartialEq, Clone, Debug)]
+#[derive(PartialEq, Clone, Debug)]
 pub enum HostPort {
     Specified(u16),
     Generated,
 }
 
+impl Serialize for HostPort {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            HostPort::Specified(port) => serializer.serialize_u16(*port),
+            HostPort::Generated => serializer.serialize_none(),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for HostPort {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -598,6 +1216,110 @@ impl<'de> Deserialize<'de> for Port {
     }
 }
 
+/// A range of host ports mapped onto a range of container ports, i.e. `8000-8010:9000-9010`
+#[derive(Debug, Clone, PartialEq)]
+struct PortRange {
+    host: RangeInclusive<u16>,
+    container: RangeInclusive<u16>,
+}
+
+fn parse_port(value: &str) -> Result<u16> {
+    u16::from_str(value.trim()).with_context(|| format!("invalid port `{}`", value))
+}
+
+fn parse_range(value: &str) -> Result<RangeInclusive<u16>> {
+    let range = match value.split_once('-') {
+        Some((start, end)) => parse_port(start)?..=parse_port(end)?,
+        None => {
+            let port = parse_port(value)?;
+            port..=port
+        }
+    };
+    if range.is_empty() {
+        bail!("port range `{}` ends before it starts", value);
+    }
+
+    Ok(range)
+}
+
+impl FromStr for PortRange {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (host_ports, container_ports) = value.split_once(':').unwrap_or((value, value));
+        let host = parse_range(host_ports)?;
+        let container = parse_range(container_ports)?;
+        if host.len() != container.len() {
+            bail!(
+                "host ports `{}` and container ports `{}` are not of the same length",
+                host_ports,
+                container_ports
+            );
+        }
+
+        Ok(PortRange { host, container })
+    }
+}
+
+impl PortRange {
+    fn expand(&self) -> Vec<Port> {
+        self.host
+            .clone()
+            .zip(self.container.clone())
+            .map(|(host, container)| Port {
+                host: HostPort::Specified(host),
+                container,
+            })
+            .collect()
+    }
+}
+
+/// A port mapping or a range of them, told apart by type so the errors of either are reported
+struct PortEntry(Vec<Port>);
+
+impl<'de> Deserialize<'de> for PortEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct PortEntryVisitor;
+
+        impl<'de> Visitor<'de> for PortEntryVisitor {
+            type Value = PortEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("port range or port struct")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                let range = PortRange::from_str(value).map_err(de::Error::custom)?;
+                Ok(PortEntry(range.expand()))
+            }
+
+            fn visit_map<M>(self, map: M) -> Result<Self::Value, M::Error>
+            where
+                M: MapAccess<'de>,
+            {
+                let port = Port::deserialize(de::value::MapAccessDeserializer::new(map))?;
+                Ok(PortEntry(vec![port]))
+            }
+        }
+
+        deserializer.deserialize_any(PortEntryVisitor)
+    }
+}
+
+fn ports<'de, D>(deserializer: D) -> Result<Vec<Port>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let entries = Vec::<PortEntry>::deserialize(deserializer)?;
+    Ok(entries.into_iter().flat_map(|entry| entry.0).collect())
+}
+
 fn deserialize_ssh<'de, D>(deserializer: D) -> Result<HashMap<String, EnvPathBuf>, D::Error>
 where
     D: Deserializer<'de>,
@@ -696,6 +1418,64 @@ where
     deserializer.deserialize_any(BuildSourceVisitor)
 }
 
+const CAPABILITY_PATTERN: &str = "^CAP_[A-Z_]+$";
+fn capabilities<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let regex = Regex::new(CAPABILITY_PATTERN).unwrap();
+    let capabilities = Vec::<String>::deserialize(deserializer)?;
+    for capability in &capabilities {
+        if !regex.is_match(capability) {
+            return Err(de::Error::invalid_value(
+                Unexpected::Str(capability),
+                &"capability name such as `CAP_NET_ADMIN`",
+            ));
+        }
+    }
+
+    Ok(capabilities)
+}
+
+const ANNOTATION_PATTERN: &str = r"^[a-zA-Z0-9]+(?:[._-][a-zA-Z0-9]+)*$";
+fn annotations<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let regex = Regex::new(ANNOTATION_PATTERN).unwrap();
+    let annotations = HashMap::<String, String>::deserialize(deserializer)?;
+    for key in annotations.keys() {
+        if !regex.is_match(key) {
+            return Err(de::Error::invalid_value(
+                Unexpected::Str(key),
+                &"annotation key such as `org.opencontainers.image.source`",
+            ));
+        }
+    }
+
+    Ok(annotations)
+}
+
+const EXTRA_HOST_PATTERN: &str =
+    r"^([a-zA-Z0-9._-]+:([0-9.]+|\[[0-9a-fA-F:]+\]|host-gateway)|host-gateway)$";
+fn extra_hosts<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let regex = Regex::new(EXTRA_HOST_PATTERN).unwrap();
+    let hosts = Vec::<String>::deserialize(deserializer)?;
+    for host in &hosts {
+        if !regex.is_match(host) {
+            return Err(de::Error::invalid_value(
+                Unexpected::Str(host),
+                &"host entry such as `example.com:127.0.0.1`",
+            ));
+        }
+    }
+
+    Ok(hosts)
+}
+
 fn registry<'de, D>(deserializer: D) -> Result<Option<RegistrySource>, D::Error>
 where
     D: Deserializer<'de>,
@@ -751,6 +1531,114 @@ where
     deserializer.deserialize_any(RegistrySourceVisitor)
 }
 
+const DURATION_PATTERN: &str = r"^(?P<value>[0-9]+)(?P<unit>ms|s|m|h|d)?$";
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let regex = Regex::new(DURATION_PATTERN).unwrap();
+    let captures = regex
+        .captures(value)
+        .with_context(|| format!("duration `{}` could not be parsed", value))?;
+
+    let amount = u64::from_str(captures.name("value").unwrap().as_str())
+        .with_context(|| format!("duration `{}` is too large", value))?;
+    let duration = match captures.name("unit").map(|unit| unit.as_str()) {
+        Some("ms") => Duration::from_millis(amount),
+        Some("m") => Duration::from_secs(amount * 60),
+        Some("h") => Duration::from_secs(amount * 60 * 60),
+        Some("d") => Duration::from_secs(amount * 60 * 60 * 24),
+        _ => Duration::from_secs(amount),
+    };
+
+    Ok(duration)
+}
+
+/// Accept the POSIX signal names with and without the `SIG` prefix, i.e. `TERM` or `SIGTERM`
+pub fn parse_signal(value: &str) -> Result<String> {
+    let upper = value.to_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    if !SIGNALS.contains(&name) {
+        bail!("unknown signal `{}`", value);
+    }
+
+    Ok(format!("SIG{}", name))
+}
+
+fn signal<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(signal) => parse_signal(&signal).map(Some).map_err(|_| {
+            de::Error::invalid_value(Unexpected::Str(&signal), &"signal such as `SIGTERM`")
+        }),
+        None => Ok(None),
+    }
+}
+
+fn duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct DurationVisitor;
+
+    impl<'de> Visitor<'de> for DurationVisitor {
+        type Value = Option<Duration>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("duration such as `30s`, `500ms`, `5m` or `1h`")
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(Some(Duration::from_secs(v)))
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            let seconds = u64::try_from(v)
+                .map_err(|_| de::Error::invalid_value(Unexpected::Signed(v), &self))?;
+            Ok(Some(Duration::from_secs(seconds)))
+        }
+
+        fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            let substituted = subst::substitute(value, &subst::Env)
+                .map_err(|err| de::Error::custom(format!("{}", err)))?;
+            let result =
+                parse_duration(&substituted).map_err(|err| de::Error::custom(err.to_string()))?;
+            Ok(Some(result))
+        }
+
+        fn visit_unit<E>(self) -> std::result::Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_none<E>(self) -> std::result::Result<Self::Value, E>
+        where
+            E: Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer.deserialize_any(DurationVisitor)
+        }
+    }
+
+    deserializer.deserialize_any(DurationVisitor)
+}
+
 pub fn hash<D>(dir: D) -> Result<String>
 where
     D: AsRef<OsStr>,
@@ -758,3 +1646,284 @@ where
     let data = dir.as_ref().as_bytes();
     Ok(format!("{:x}", Sha256::digest(data)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_source_with_digest_algorithms() {
+        for algorithm in ["sha256", "sha512", "sha3-256", "blake3"] {
+            let value = format!("alpine@{}:abcdef0123", algorithm);
+            let source = RegistrySource::try_from(value.as_str()).unwrap();
+            assert_eq!(source.repository, "alpine");
+            match source.reference {
+                Reference::Digest(digest) => {
+                    assert_eq!(digest.to_string(), format!("{}:abcdef0123", algorithm))
+                }
+                Reference::Tag(tag) => panic!("expected digest, got tag `{}`", tag),
+            }
+        }
+    }
+
+    #[test]
+    fn registry_source_with_unsupported_algorithm() {
+        assert!(RegistrySource::try_from("alpine@md5:abcdef").is_err());
+    }
+
+    fn built(build_args: &[(&str, &str)]) -> ContainerConfig {
+        let build_args = build_args
+            .iter()
+            .map(|(name, value)| (name.to_string(), EnvSub::from(value.to_string())))
+            .collect();
+        ContainerConfig {
+            build: Some(BuildSource {
+                context: PathBuf::from("."),
+                build_args,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_hash_changes_with_build_args() {
+        let first = built(&[("VERSION", "1")]).content_hash(None).unwrap();
+        let second = built(&[("VERSION", "2")]).content_hash(None).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            first,
+            built(&[("VERSION", "1")]).content_hash(None).unwrap()
+        );
+    }
+
+    #[test]
+    fn content_hash_changes_with_build_file() {
+        let config = built(&[]);
+        let first = config.content_hash(Some(b"FROM alpine:3.15")).unwrap();
+        let second = config.content_hash(Some(b"FROM alpine:3.16")).unwrap();
+        assert_ne!(first, second);
+    }
+
+    fn container_ports(yaml: &str) -> Result<Vec<Port>> {
+        let config = Config::new(format!("containers:\n  app:\n    ports:\n{}", yaml).as_bytes())?;
+        Ok(config.containers["app"].ports.clone())
+    }
+
+    #[test]
+    fn port_range_is_expanded() {
+        let ports = container_ports("      - 8000-8002:9000-9002\n      - \"3000\"\n").unwrap();
+        let expected: Vec<(u16, u16)> =
+            vec![(8000, 9000), (8001, 9001), (8002, 9002), (3000, 3000)];
+        assert_eq!(
+            ports,
+            expected
+                .into_iter()
+                .map(|(host, container)| Port {
+                    host: HostPort::Specified(host),
+                    container,
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn port_range_of_mismatched_length_is_rejected() {
+        let error = container_ports("      - 8000-8002:9000-9001\n").unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("are not of the same length"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn port_struct_reports_its_own_error() {
+        let ports = container_ports("      - host: 8080\n        container: 80\n").unwrap();
+        assert_eq!(ports[0].host, HostPort::Specified(8080));
+        assert_eq!(ports[0].container, 80);
+
+        let error = container_ports("      - host: 8080\n        container: http\n").unwrap_err();
+        assert!(
+            !format!("{:#}", error).contains("did not match any variant"),
+            "{:#}",
+            error
+        );
+    }
+
+    fn parse(yaml: &str) -> Config {
+        Config::new(yaml.as_bytes()).unwrap()
+    }
+
+    fn env(container: &ContainerConfig, name: &str) -> String {
+        container.env[name].clone().into_inner()
+    }
+
+    #[test]
+    fn extended_container_overrides_its_base() {
+        let config = parse(
+            "containers:
+  base:
+    image: alpine:3
+    cmd: sh
+    env:
+      MODE: base
+  middle:
+    extends: base
+    cmd: bash
+  app:
+    extends: middle
+    env:
+      MODE: app
+",
+        );
+
+        let app = &config.containers["app"];
+        assert_eq!(app.image.as_ref().unwrap().repository, "alpine");
+        assert_eq!(app.cmd.as_deref(), Some("bash"));
+        assert_eq!(env(app, "MODE"), "app");
+        assert_eq!(env(&config.containers["middle"], "MODE"), "base");
+    }
+
+    #[test]
+    fn circular_extends_is_rejected() {
+        let error = Config::new(
+            "containers:
+  a:
+    extends: b
+  b:
+    extends: a
+"
+            .as_bytes(),
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", error).contains("circular `extends` detected"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn selected_profile_overrides_containers() {
+        let yaml = "containers:
+  app:
+    image: alpine:3
+    cmd: sh
+profiles:
+  ci:
+    containers:
+      app:
+        cmd: ash
+      lint:
+        image: node:18
+  dev:
+    containers:
+      app:
+        cmd: bash
+";
+        let mut config = parse(yaml);
+        config.apply_profile("ci");
+
+        let app = &config.containers["app"];
+        assert_eq!(app.cmd.as_deref(), Some("ash"));
+        assert_eq!(app.image.as_ref().unwrap().repository, "alpine");
+        assert!(config.containers.contains_key("lint"));
+
+        let mut config = parse(yaml);
+        config.apply_profile("missing");
+        assert_eq!(config.containers["app"].cmd.as_deref(), Some("sh"));
+        assert!(!config.containers.contains_key("lint"));
+    }
+
+    #[test]
+    fn defaults_do_not_override_explicit_values() {
+        let config = parse(
+            "defaults:
+  workdir: /default
+  read_only: true
+  env:
+    MODE: default
+    SHARED: default
+containers:
+  app:
+    image: alpine:3
+    workdir: /app
+    read_only: false
+    env:
+      MODE: app
+  other:
+    image: alpine:3
+",
+        );
+
+        let app = &config.containers["app"];
+        assert_eq!(app.workdir.as_deref(), Some(Path::new("/app")));
+        assert_eq!(app.read_only, Some(false));
+        assert_eq!(env(app, "MODE"), "app");
+        assert_eq!(env(app, "SHARED"), "default");
+
+        let other = &config.containers["other"];
+        assert_eq!(other.workdir.as_deref(), Some(Path::new("/default")));
+        assert_eq!(other.read_only, Some(true));
+        assert_eq!(env(other, "MODE"), "default");
+    }
+
+    #[test]
+    fn json_and_yaml_configurations_are_equivalent() {
+        let yaml = parse(
+            "containers:
+  app:
+    image: alpine:3
+    cmd: sh
+    args: [-c, true]
+    ports:
+      - 8080:80
+volumes:
+  cache:
+    type: tmpfs
+",
+        );
+        let json = parse(
+            r#"{
+  "containers": {
+    "app": {"image": "alpine:3", "cmd": "sh", "args": ["-c", "true"], "ports": ["8080:80"]}
+  },
+  "volumes": {"cache": {"type": "tmpfs"}}
+}"#,
+        );
+
+        assert_eq!(
+            serde_json::to_value(&yaml).unwrap(),
+            serde_json::to_value(&json).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_json_reports_the_json_error() {
+        let error =
+            Config::new(r#"{"containers": {"app": {"cmd": "sh"}}} }"#.as_bytes()).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("trailing characters"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(
+            parse_duration("24h").unwrap(),
+            Duration::from_secs(24 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration("7d").unwrap(),
+            Duration::from_secs(7 * 24 * 60 * 60)
+        );
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("10").unwrap(), Duration::from_secs(10));
+        assert!(parse_duration("7w").is_err());
+    }
+}
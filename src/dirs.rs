@@ -1,6 +1,6 @@
 use std::ffi::OsStr;
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::{env, fs};
 
 use anyhow::{Context, Result};
 use directories::{BaseDirs, ProjectDirs};
@@ -37,6 +37,17 @@ where
     Ok(directory)
 }
 
+fn config_dir<P>(sub_directory: P) -> Result<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let project_directories = project_directories()?;
+    let config_directory = project_directories.config_dir();
+    let mut directory: PathBuf = config_directory.into();
+    directory.push(sub_directory);
+    Ok(directory)
+}
+
 fn state_dir<P>(sub_directory: P) -> Result<PathBuf>
 where
     P: AsRef<Path>,
@@ -70,7 +81,18 @@ where
     }
 }
 
-fn images() -> Result<PathBuf> {
+/// All application directories with a descriptive name
+pub fn directories() -> Result<Vec<(&'static str, PathBuf)>> {
+    Ok(vec![
+        ("cache", cache_dir("")?),
+        ("config", config_dir("")?),
+        ("data", data_dir("")?),
+        ("state", state_dir("")?),
+        ("runtime", run_dir("")?),
+    ])
+}
+
+pub fn images() -> Result<PathBuf> {
     state_dir("images")
 }
 
@@ -78,7 +100,32 @@ pub fn scripts() -> Result<PathBuf> {
     state_dir("scripts")
 }
 
-fn volumes_dir() -> Result<PathBuf> {
+pub fn last_update_check() -> Result<PathBuf> {
+    state_dir("last-update-check")
+}
+
+/// Directory with the detached containers of the configuration
+pub fn running_dir<D>(config_dir: D) -> Result<PathBuf>
+where
+    D: AsRef<OsStr>,
+{
+    let digest = config::hash(config_dir)?;
+    let mut dir = state_dir("running")?;
+    dir.push(digest);
+    Ok(dir)
+}
+
+/// File with the name of the detached container
+pub fn running<D>(config_dir: D, container_name: &str) -> Result<PathBuf>
+where
+    D: AsRef<OsStr>,
+{
+    let mut path = running_dir(config_dir)?;
+    path.push(container_name);
+    Ok(path)
+}
+
+pub fn volumes_dir() -> Result<PathBuf> {
     data_dir("volumes")
 }
 
@@ -118,7 +165,22 @@ where
     Ok(dir)
 }
 
+pub fn global_config() -> Result<PathBuf> {
+    config_dir("config.yaml")
+}
+
+/// Overrides the socket path, set by the `--socket-path` flag
+pub const SOCKET_PATH_VARIABLE: &str = "TOIP_SOCKET_PATH";
+/// Directory to create the socket in when the runtime directory is not writable
+pub const SOCKET_DIR_VARIABLE: &str = "TOIP_SOCK_DIR";
+
 pub fn socket_path() -> Result<PathBuf> {
+    if let Some(path) = env::var_os(SOCKET_PATH_VARIABLE) {
+        return Ok(PathBuf::from(path));
+    }
+    if let Some(dir) = env::var_os(SOCKET_DIR_VARIABLE) {
+        return Ok(PathBuf::from(dir).join("socket"));
+    }
     run_dir("socket")
 }
 
@@ -126,6 +188,16 @@ pub fn create(dir: &Path) -> anyhow::Result<()> {
     fs::create_dir_all(dir).with_context(|| format!("could not create directory `{:#?}`", dir))
 }
 
+/// Directory of the systemd user units
+pub fn systemd_units() -> Result<PathBuf> {
+    let dirs = BaseDirs::new().context("could not determine home directory")?;
+    let mut dir = dirs.config_dir().to_path_buf();
+    dir.push("systemd");
+    dir.push("user");
+
+    Ok(dir)
+}
+
 pub fn path() -> Result<PathBuf> {
     let dirs = BaseDirs::new().context("could not determine home directory")?;
     let bin_dir = dirs
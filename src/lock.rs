@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::config::{Config, Digest, Reference};
+
+pub const LOCK_FILE_NAME: &str = "toip.lock";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedImage {
+    pub repository: String,
+    pub digest: Digest,
+}
+
+/// Resolved image digests of the containers, stored next to the configuration file
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lock {
+    #[serde(default)]
+    pub containers: BTreeMap<String, LockedImage>,
+}
+
+fn lock_path(config_dir: &Path) -> PathBuf {
+    config_dir.join(LOCK_FILE_NAME)
+}
+
+impl Lock {
+    /// Whether the project pins its images, created by `toip lock`
+    pub fn exists(config_dir: &Path) -> bool {
+        lock_path(config_dir).is_file()
+    }
+
+    pub fn load(config_dir: &Path) -> Result<Lock> {
+        let path = lock_path(config_dir);
+        if !path.is_file() {
+            return Ok(Lock::default());
+        }
+
+        let file = File::open(&path)
+            .with_context(|| format!("could not read lock file `{}`", path.display()))?;
+        serde_yaml::from_reader(file)
+            .with_context(|| format!("could not parse lock file `{}`", path.display()))
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        let path = lock_path(config_dir);
+        let yaml = serde_yaml::to_string(self).context("could not serialize lock file")?;
+        fs::write(&path, yaml)
+            .with_context(|| format!("could not write lock file `{}`", path.display()))
+    }
+
+    pub fn record(&mut self, container_name: &str, repository: &str, digest: Digest) {
+        self.containers.insert(
+            container_name.to_string(),
+            LockedImage {
+                repository: repository.to_string(),
+                digest,
+            },
+        );
+    }
+
+    /// Reference the locked digests instead of the configured tags
+    pub fn apply(&self, config: &mut Config) {
+        for (name, container) in config.containers.iter_mut() {
+            let image = match container.image.as_mut() {
+                Some(image) => image,
+                None => continue,
+            };
+
+            match self.containers.get(name) {
+                Some(locked) if locked.repository == image.repository => {
+                    log::debug!("using locked digest `{}` for `{}`", locked.digest, name);
+                    image.reference = Reference::Digest(locked.digest.clone());
+                }
+                Some(_) => log::warn!(
+                    "ignoring lock of container `{}` as its image changed, run `toip lock --update`",
+                    name
+                ),
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    fn digest(value: &str) -> Digest {
+        Digest::try_from(value).unwrap()
+    }
+
+    #[test]
+    fn serializes_containers_in_order() {
+        let mut lock = Lock::default();
+        for name in &["zsh", "alpine", "node"] {
+            lock.record(name, name, digest("sha256:aaaa"));
+        }
+
+        let yaml = serde_yaml::to_string(&lock).unwrap();
+        let positions: Vec<usize> = ["alpine:", "node:", "zsh:"]
+            .iter()
+            .map(|name| yaml.find(name).unwrap())
+            .collect();
+        assert!(
+            positions.windows(2).all(|pair| pair[0] < pair[1]),
+            "{}",
+            yaml
+        );
+    }
+
+    #[test]
+    fn applies_locked_digest_of_same_repository() {
+        let mut config = Config::new(
+            "containers:\n  tool:\n    image: alpine:3\n  other:\n    image: node:18\n".as_bytes(),
+        )
+        .unwrap();
+        let mut lock = Lock::default();
+        lock.record("tool", "alpine", digest("sha256:aaaa"));
+        lock.record("other", "debian", digest("sha256:bbbb"));
+
+        lock.apply(&mut config);
+
+        let reference = |name: &str| config.containers[name].image.clone().unwrap().reference;
+        assert_eq!(reference("tool"), Reference::Digest(digest("sha256:aaaa")));
+        assert_eq!(reference("other"), Reference::Tag("18".into()));
+    }
+}
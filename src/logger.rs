@@ -1,6 +1,16 @@
-use anyhow::Result;
-use log::Level;
-use simplelog::{ColorChoice, ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use log::{Level, Log, Metadata, Record};
+use serde_json::json;
+use simplelog::{
+    ColorChoice, CombinedLogger, Config, ConfigBuilder, LevelFilter, SharedLogger, TermLogger,
+    TerminalMode,
+};
 
 fn level_filter(level: Option<Level>) -> LevelFilter {
     match level {
@@ -12,7 +22,75 @@ fn level_filter(level: Option<Level>) -> LevelFilter {
         None => LevelFilter::Off,
     }
 }
-pub fn init(level: Option<Level>) -> Result<()> {
+
+/// Writes every record as a JSON object on its own line
+struct JsonLogger {
+    level: LevelFilter,
+    file: Mutex<File>,
+}
+
+impl JsonLogger {
+    fn new(path: &Path, level: LevelFilter) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open log file `{}`", path.display()))?;
+
+        Ok(JsonLogger {
+            level,
+            file: Mutex::new(file),
+        })
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |duration| duration.as_secs_f64());
+        let line = json!({
+            "time": time,
+            "level": record.level().to_string(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        });
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+impl SharedLogger for JsonLogger {
+    fn level(&self) -> LevelFilter {
+        self.level
+    }
+
+    fn config(&self) -> Option<&Config> {
+        None
+    }
+
+    fn as_log(self: Box<Self>) -> Box<dyn Log> {
+        Box::new(*self)
+    }
+}
+
+/// The terminal logger, combined with a JSON logger when logging to a file
+fn loggers(level: Option<Level>, log_file: Option<&Path>) -> Result<Vec<Box<dyn SharedLogger>>> {
     let config = ConfigBuilder::new()
         .set_max_level(LevelFilter::Error)
         .set_time_level(LevelFilter::Error)
@@ -21,13 +99,92 @@ pub fn init(level: Option<Level>) -> Result<()> {
         .set_location_level(LevelFilter::Off)
         .build();
 
-    TermLogger::init(
-        // LevelFilter::Trace,
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
         level_filter(level),
         config,
         TerminalMode::Stderr,
         ColorChoice::Auto,
-    )?;
+    )];
+    if let Some(path) = log_file {
+        // The file is read after the fact, so it records more than the terminal
+        let level = level_filter(level).max(LevelFilter::Debug);
+        loggers.push(Box::new(JsonLogger::new(path, level)?));
+    }
+
+    Ok(loggers)
+}
+
+pub fn init(level: Option<Level>, log_file: Option<&Path>) -> Result<()> {
+    CombinedLogger::init(loggers(level, log_file)?).context("logger is already initialized")?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn json_logger_writes_lines() {
+        let path = env::temp_dir().join(format!("toip-log-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+        let logger = JsonLogger::new(&path, LevelFilter::Info).unwrap();
+
+        logger.log(
+            &Record::builder()
+                .args(format_args!("pulled image"))
+                .level(Level::Info)
+                .target("toip")
+                .build(),
+        );
+        logger.log(
+            &Record::builder()
+                .args(format_args!("filtered"))
+                .level(Level::Debug)
+                .build(),
+        );
+        logger.flush();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["target"], "toip");
+        assert_eq!(lines[0]["message"], "pulled image");
+        assert!(lines[0]["time"].is_f64());
+    }
+
+    #[test]
+    fn log_file_records_more_than_terminal() {
+        let path = env::temp_dir().join(format!("toip-log-combined-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+        let logger = CombinedLogger::new(loggers(Some(Level::Warn), Some(&path)).unwrap());
+        assert_eq!(logger.level(), LevelFilter::Debug);
+
+        logger.log(
+            &Record::builder()
+                .args(format_args!("resolved driver"))
+                .level(Level::Debug)
+                .build(),
+        );
+        logger.flush();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<Value> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "DEBUG");
+        assert_eq!(lines[0]["message"], "resolved driver");
+    }
+}
@@ -1,14 +1,22 @@
-use std::env;
+use std::path::PathBuf;
 use std::process::{self};
+use std::{env, fs};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{Context, Result};
 use backend::script;
 use clap::Parser;
 use server::CallInfo;
 
-use crate::cli::{Arguments, Cli, Command};
-use crate::command::{call, inject, install, prepare, run};
-use crate::config::{find_config_file, Config};
+use crate::backend::driver::DRIVER_VARIABLE;
+use crate::backend::INHERIT_ENVVARS_VARIABLE;
+use crate::cli::{script_arguments, Cli, Command};
+use crate::command::{
+    add, call, clean, completions, debug, doctor, envargs, exec, export, generate, import, info,
+    init, inject, inspect, install, kill, lock, login, logout, logs, prepare, ps, pull, run, shell,
+    status, stop, template, update, validate, version, volume,
+};
+use crate::config::{CONFIG_VARIABLE, PROFILE_VARIABLE};
+use crate::dirs::SOCKET_PATH_VARIABLE;
 
 mod backend;
 mod cli;
@@ -16,6 +24,7 @@ mod command;
 mod config;
 mod dirs;
 mod dotenv;
+mod lock;
 mod logger;
 mod metadata;
 mod server;
@@ -24,45 +33,182 @@ mod server;
 async fn main() -> Result<()> {
     dotenv::load().context("could not load environment variables")?;
 
-    let cli = Cli::parse();
-    logger::init(cli.verbose.log_level()).context("could not initialize logger")?;
+    let cli = Cli::parse_from(script_arguments(env::args_os()));
+    logger::init(cli.verbose.log_level(), cli.log_file.as_deref())
+        .context("could not initialize logger")?;
+    if let Some(profile) = &cli.profile {
+        env::set_var(PROFILE_VARIABLE, profile);
+    }
+    if let Some(driver) = &cli.driver {
+        env::set_var(DRIVER_VARIABLE, driver);
+    }
+    if let Some(socket_path) = &cli.socket_path {
+        // Containers mount the socket, so it must be absolute
+        env::set_var(SOCKET_PATH_VARIABLE, env::current_dir()?.join(socket_path));
+    }
+    if let Some(config) = &cli.config {
+        // Scripts and volumes are keyed by the configuration directory, so it must be absolute
+        let config = fs::canonicalize(config)
+            .with_context(|| format!("could not find configuration `{}`", config.display()))?;
+        env::set_var(CONFIG_VARIABLE, config);
+    }
     log::trace!("current pid is `{}`", process::id());
 
     match cli.command {
-        Command::Run { script, args } => {
-            let actual_args = match args {
-                Some(Arguments::Arguments(arg)) => arg,
-                None => vec![],
-            };
-            run(script, actual_args).await
+        Command::Run {
+            platform,
+            allow_privileged,
+            watch,
+            detach,
+            script,
+            args,
+        } => {
+            let code = run(
+                script,
+                args,
+                platform,
+                allow_privileged,
+                watch,
+                detach,
+                !cli.no_global_config,
+            )
+            .await?;
+            process::exit(code)
         }
         Command::Call { script, args } => {
             let container_name = script::read_container(script)?;
-            let socket_path = env::var("TOIP_SOCK")
-                .context("environment variable `TOIP_SOCK` does not exists")?;
-
-            let actual_args = match args {
-                Some(Arguments::Arguments(arg)) => arg,
-                None => vec![],
+            let socket_path = match cli.socket_path {
+                Some(socket_path) => socket_path,
+                None => env::var_os("TOIP_SOCK")
+                    .map(PathBuf::from)
+                    .context("environment variable `TOIP_SOCK` does not exists")?,
             };
-            call(socket_path, &container_name, actual_args)
+
+            let inherited = env::var(INHERIT_ENVVARS_VARIABLE).unwrap_or_default();
+            let names: Vec<&str> = inherited
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .collect();
+            call(socket_path, &container_name, args, envargs(&names))
                 .with_context(|| format!("could not call container `{}`", container_name))
         }
         Command::Prepare {
             container,
             ignore_missing,
-        } => prepare(ignore_missing, container).await,
-        Command::Install { ignore_missing } => install(ignore_missing),
+            platform,
+            allow_privileged,
+            json,
+            dry_run,
+            progress,
+            no_cache,
+        } => {
+            prepare(
+                ignore_missing,
+                container,
+                platform,
+                allow_privileged,
+                json,
+                dry_run,
+                progress,
+                no_cache,
+                !cli.no_global_config,
+            )
+            .await
+        }
+        Command::Pull {
+            container,
+            ignore_missing,
+            platform,
+        } => pull(ignore_missing, container, platform, !cli.no_global_config).await,
+        Command::Lock { update } => lock(update, !cli.no_global_config).await,
+        Command::Login { registry } => login(registry).await,
+        Command::Logout { registry } => logout(registry).await,
+        Command::Update { container, apply } => {
+            update(container, apply, !cli.no_global_config).await
+        }
+        Command::Install {
+            ignore_missing,
+            dry_run,
+        } => install(ignore_missing, dry_run, !cli.no_global_config),
+        Command::Volume { command } => volume(command, !cli.no_global_config).await,
+        Command::Generate { target } => generate(target),
         Command::Inject { shell } => inject(shell),
-        Command::Debug {} => {
-            let current_dir = env::current_dir()?;
-            let config_path = find_config_file(current_dir)
-                .ok_or_else(|| anyhow!("Unable to find config file"))?;
-            let config_dir = config_path.parent().unwrap().to_path_buf();
-            let config = Config::new_from_dir(&config_dir)?;
-            dbg!(config);
-            Ok(())
+        Command::Init { force } => init(force),
+        Command::Export { output } => export(output),
+        Command::Import { file, force } => import(file, force),
+        Command::Add {
+            name,
+            image,
+            build_context,
+            links,
+            force,
+        } => add(name, image, build_context, links, force),
+        Command::Template {
+            name,
+            output,
+            registry,
+            values,
+            inherit,
+            force,
+        } => template(name, output, registry, values, inherit, force),
+        Command::Version { check } => version(check),
+        Command::Doctor {} => doctor().await,
+        Command::Info {} => info().await,
+        Command::Validate { strict } => validate(strict),
+        Command::Status { watch } => status(watch, !cli.no_global_config).await,
+        Command::Ps { all } => ps(all, !cli.no_global_config).await,
+        Command::Exec {
+            workdir,
+            container,
+            command,
+        } => {
+            let code = exec(container, command, workdir, !cli.no_global_config).await?;
+            process::exit(code)
+        }
+        Command::Stop {
+            timeout,
+            all,
+            container,
+        } => stop(container, all, timeout, !cli.no_global_config).await,
+        Command::Kill { signal, container } => kill(container, signal, !cli.no_global_config).await,
+        Command::Logs {
+            follow,
+            tail,
+            since,
+            container,
+        } => {
+            let code = logs(container, follow, tail, since, !cli.no_global_config).await?;
+            process::exit(code)
+        }
+        Command::Shell {
+            platform,
+            allow_privileged,
+            shell: shell_command,
+            container,
+        } => {
+            let code = shell(
+                container,
+                shell_command,
+                platform,
+                allow_privileged,
+                !cli.no_global_config,
+            )
+            .await?;
+            process::exit(code)
         }
-        _ => todo!(),
+        Command::Inspect {
+            raw,
+            field,
+            container,
+        } => inspect(container, raw, field, !cli.no_global_config),
+        Command::Completions { dynamic } => completions(dynamic),
+        Command::Debug { format } => debug(format),
+        Command::Clean {
+            containers,
+            networks,
+            container,
+            older_than,
+            blobs_only,
+        } => clean(containers, networks, container, older_than, blobs_only).await,
     }
 }
@@ -1,4 +1,4 @@
-// pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const APPLICATION_NAME: &str = env!("CARGO_PKG_NAME");
 // pub const HOMEPAGE: &str = env!("CARGO_PKG_HOMEPAGE");
 pub const QUALIFIER: &str = "dev";
@@ -1,16 +1,22 @@
 use std::collections::HashMap;
+use std::os::unix::fs::FileTypeExt;
 use std::os::unix::net::UnixStream;
 use std::os::unix::prelude::RawFd;
-use std::path::Path;
-use std::str;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, process, str};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use futures_util::stream::FuturesUnordered;
 use futures_util::StreamExt;
 use itertools::join;
 use serde_derive::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
 use tokio::net::UnixListener;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinError};
 use tokio_stream::wrappers::UnixListenerStream;
 use tokio_util::sync::CancellationToken;
 use uds::UnixStreamExt;
@@ -28,6 +34,35 @@ pub struct CallInfo {
     pub envargs: HashMap<String, String>,
 }
 
+/// Connections handled at the same time, further callers are turned away
+const MAX_CONCURRENT_CONNECTIONS: usize = 64;
+/// How long a caller may take to send its call
+const RECEIVE_TIMEOUT: Duration = Duration::from_secs(10);
+const BUSY_MESSAGE: &[u8] = br#"{"error": "server busy"}"#;
+
+/// Read the sent file descriptors and the call, blocks until the caller sent them
+fn receive(stream: UnixStream) -> Result<(CallInfo, [RawFd; 3])> {
+    stream
+        .set_nonblocking(false)
+        .context("could not make stream blocking")?;
+    stream
+        .set_read_timeout(Some(RECEIVE_TIMEOUT))
+        .context("could not set read timeout")?;
+
+    let mut data = [0; 1024];
+    let mut file_descriptors = [0; 3];
+    stream.recv_fds(&mut data, &mut file_descriptors)?;
+
+    let message_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if message_size + 4 > data.len() {
+        bail!("call of {} bytes does not fit the buffer", message_size);
+    }
+
+    let info: CallInfo = serde_json::from_slice(&data[4..message_size + 4])?;
+
+    Ok((info, file_descriptors))
+}
+
 struct Inner {
     sender: Sender<Call>,
 }
@@ -36,20 +71,11 @@ impl Inner {
     // Handle a connection, read the sent file descriptors and read the send call instructions
     async fn handle(&self, stream: UnixStream) -> Result<()> {
         log::info!("handling incoming connection");
-        // TODO implement bidirectional communication.
-        // Host should communicate the inherited envvars so the client only send
-        // the env vars needed, limiting the exposure of envvars
-
-        let mut data = [0; 1024];
-        let mut file_descriptors = [0; 3];
-        stream.recv_fds(&mut data, &mut file_descriptors)?;
-
-        let message_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
-        if message_size >= 1024 {
-            panic!("Message size to large for single buffer"); // TODO allow arbitrary buffer size
-        }
 
-        let info: CallInfo = serde_json::from_slice(&data[4..message_size + 4])?;
+        // Receiving blocks, so keep it off the runtime's worker threads
+        let (info, file_descriptors) = task::spawn_blocking(move || receive(stream))
+            .await
+            .context("could not join receiving thread")??;
         log::info!(
             "received call for `{}`, with file descriptors `{}`",
             info.name,
@@ -71,11 +97,14 @@ pub struct Server {
     cancellation_token: CancellationToken,
     listener_stream: UnixListenerStream,
     inner: Arc<Inner>,
+    pid_path: PathBuf,
 }
 
 impl Server {
     pub async fn listen(mut self) -> Result<()> {
         let cancellation_token = &self.cancellation_token;
+        let mut handlers = FuturesUnordered::new();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CONNECTIONS));
 
         loop {
             tokio::select! {
@@ -85,19 +114,92 @@ impl Server {
                     let inner = self.inner.clone();
                     log::trace!("accepted incoming connection");
 
+                    let permit = match semaphore.clone().try_acquire_owned() {
+                        Ok(permit) => permit,
+                        Err(_) => {
+                            log::warn!("rejecting connection, {} calls are being handled", MAX_CONCURRENT_CONNECTIONS);
+                            reject(stream).await;
+                            continue;
+                        }
+                    };
+
                     let std_stream = stream
                         .into_std()
                         .context("could not convert Tokio's UnixStream to std's UnixStream")?;
-                    inner.handle(std_stream).await.context("could not handle stream")?;
+                    // Handle connections concurrently, so a slow caller does not block the others
+                    handlers.push(tokio::spawn(async move {
+                        let handled = inner.handle(std_stream).await;
+                        drop(permit);
+                        handled
+                    }));
                 },
+                Some(handled) = handlers.next() => log_handled(handled),
                 _ = cancellation_token.cancelled() => break,
                 else => break,
             }
         }
 
+        // Drain the connections which are still being handled
+        while let Some(handled) = handlers.next().await {
+            log_handled(handled);
+        }
+
         log::info!("stopped listening on call socket");
-        Ok(())
+        fs::remove_file(&self.pid_path)
+            .with_context(|| format!("could not remove pid file `{}`", self.pid_path.display()))
+    }
+}
+
+/// Tell the caller the server is busy, it is closed when dropped
+async fn reject(mut stream: tokio::net::UnixStream) {
+    if let Err(error) = stream.write_all(BUSY_MESSAGE).await {
+        log::debug!("could not reject connection: {}", error);
+    }
+}
+
+fn log_handled(handled: std::result::Result<Result<()>, JoinError>) {
+    match handled {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => log::error!("could not handle stream: {:#}", error),
+        Err(error) => log::error!("could not join connection handler: {}", error),
+    }
+}
+
+/// Remove the socket left behind by a crashed instance, fail when it is still in use
+fn remove_stale_socket(socket_path: &Path, pid_path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(socket_path)
+        .with_context(|| format!("could not read `{}`", socket_path.display()))?;
+    if !metadata.file_type().is_socket() {
+        bail!(
+            "`{}` exists and is not a socket, refusing to remove it",
+            socket_path.display()
+        );
+    }
+
+    if UnixStream::connect(socket_path).is_ok() {
+        let pid = fs::read_to_string(pid_path).unwrap_or_default();
+        match pid.trim() {
+            "" => bail!(
+                "another toip instance is already running at `{}`",
+                socket_path.display()
+            ),
+            pid => bail!(
+                "another toip instance (pid {}) is already running at `{}`",
+                pid,
+                socket_path.display()
+            ),
+        }
     }
+
+    log::warn!("removing stale socket `{}`", socket_path.display());
+    fs::remove_file(socket_path)
+        .with_context(|| format!("could not remove stale socket `{}`", socket_path.display()))?;
+    if pid_path.exists() {
+        fs::remove_file(pid_path)
+            .with_context(|| format!("could not remove pid file `{}`", pid_path.display()))?;
+    }
+
+    Ok(())
 }
 
 pub fn create<S>(
@@ -110,9 +212,16 @@ where
 {
     let socket_path = socket_path.as_ref();
     let path = socket_path.to_string_lossy();
+    let pid_path = socket_path.with_extension("pid");
+    if socket_path.exists() {
+        remove_stale_socket(socket_path, &pid_path)?;
+    }
+
     log::info!("listening on `{}`", path);
     let listener = UnixListener::bind(socket_path)
         .with_context(|| format!("could not listen on socket `{}`", path))?;
+    fs::write(&pid_path, process::id().to_string())
+        .with_context(|| format!("could not write pid file `{}`", pid_path.display()))?;
 
     let unix_stream = UnixListenerStream::new(listener);
 
@@ -120,5 +229,99 @@ where
         cancellation_token,
         listener_stream: unix_stream,
         inner: Arc::new(Inner { sender }),
+        pid_path,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+    use std::io::Read;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::command::call;
+
+    fn socket_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("toip-{}-{}.sock", name, process::id()))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn receives_call() {
+        let socket = socket_path("receive");
+        let (tx, mut rx) = mpsc::channel(1);
+        let token = CancellationToken::new();
+        let server = create(&socket, tx, token.clone()).unwrap();
+        let listening = tokio::spawn(server.listen());
+
+        let caller = socket.clone();
+        task::spawn_blocking(move || {
+            call(&caller, "alpine", vec!["ls".to_string()], HashMap::new())
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let received = rx.recv().await.unwrap();
+        assert_eq!(received.info.name, "alpine");
+        assert_eq!(received.info.arguments, vec!["ls"]);
+
+        token.cancel();
+        listening.await.unwrap().unwrap();
+        fs::remove_file(&socket).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejects_excess_connections() {
+        let socket = socket_path("reject");
+        let (tx, _rx) = mpsc::channel(1);
+        let token = CancellationToken::new();
+        let server = create(&socket, tx, token.clone()).unwrap();
+        let listening = tokio::spawn(server.listen());
+
+        // Silent callers hold on to their connection until they are dropped
+        let mut silent = vec![];
+        for _ in 0..MAX_CONCURRENT_CONNECTIONS {
+            silent.push(UnixStream::connect(&socket).unwrap());
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let busy = socket.clone();
+        let response = task::spawn_blocking(move || {
+            let mut stream = UnixStream::connect(busy).unwrap();
+            let mut response = vec![];
+            stream.read_to_end(&mut response).unwrap();
+            response
+        })
+        .await
+        .unwrap();
+        assert_eq!(response, BUSY_MESSAGE);
+
+        drop(silent);
+        token.cancel();
+        listening.await.unwrap().unwrap();
+        fs::remove_file(&socket).unwrap();
+    }
+
+    #[test]
+    fn keeps_regular_file_at_socket_path() {
+        let socket = socket_path("regular");
+        fs::write(&socket, "data").unwrap();
+
+        let result = remove_stale_socket(&socket, &socket.with_extension("pid"));
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&socket).unwrap(), "data");
+        fs::remove_file(&socket).unwrap();
+    }
+
+    #[test]
+    fn removes_stale_socket() {
+        let socket = socket_path("stale");
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+        remove_stale_socket(&socket, &socket.with_extension("pid")).unwrap();
+        assert!(!socket.exists());
+    }
+}